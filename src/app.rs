@@ -1,24 +1,51 @@
+mod cache;
+mod cli;
+mod config;
+mod conn;
+mod history;
 mod images;
 mod imp;
+mod keys;
+mod library;
+mod proto;
+mod queue;
 mod search;
+mod transport;
 pub mod utils;
+mod worker;
 
-use std::{net::TcpStream, time::Instant};
+use std::{
+    sync::mpsc::channel,
+    time::{Duration, Instant}
+};
 
 use eframe::{
     egui::{
-        style::Selection, Align, CentralPanel, CtxRef, FontData, FontDefinitions, FontFamily, Key, Layout,
+        epaint::text::cursor::CCursor,
+        style::Selection,
+        widgets::text_edit::{CCursorRange, TextEditState},
+        Align, Align2, Area, Button, CentralPanel, CtxRef, FontData, FontDefinitions, FontFamily, Layout,
         RichText, ScrollArea, SidePanel, Slider, Stroke, TextEdit, TextStyle, Vec2
     },
     epi,
     epi::Frame,
     run_native, NativeOptions
 };
-use mpd::{Client, State, Status};
-
-use self::imp::{Rinse, Setup, SongInfo, Update};
+use mpd::{State, Status};
+
+use self::{
+    config::Config,
+    conn::Connection,
+    imp::{Mode, Rinse, Setup, SongInfo, Update},
+    keys::Action,
+    library::Add,
+    queue::Edit,
+    search::{Matching, Scorer, Searcher},
+    transport::Control,
+    worker::Event
+};
 
-pub fn start(stuff: (Client<TcpStream>, Status, String)) {
+pub fn start(stuff: (Connection, Status, String, Config)) {
     let options = NativeOptions {
         always_on_top:         true,
         maximized:             false,
@@ -80,33 +107,165 @@ impl epi::App for Rinse {
 
         ctx.set_fonts(fonts);
 
-        let (music_dir, filepath) = &data.paths;
-        let image = images::get_cover(&(music_dir, filepath));
-        let texture = frame.alloc_texture(image);
-        let size = [250.0, 250.0].into();
-        data.cover = Some((size, texture));
+        data.placeholder = Some(frame.alloc_texture(images::placeholder()));
 
         data.switcher_timer = Some(Instant::now());
-        data.update_timer = Some(Instant::now())
+        let (tx, rx) = channel();
+        worker::spawn(
+            data.mpc.settings.clone(),
+            data.status.queue_version,
+            tx.clone(),
+            frame.clone()
+        );
+        let searcher = Searcher::spawn(&data.config, tx.clone(), frame.clone());
+        searcher.update_queue(data.queue.clone(), data.queue.len());
+        // nothing to pick from in an empty queue, so start on the library
+        if data.queue.is_empty() {
+            data.mode = Mode::Library;
+            worker::load_library(
+                data.mpc.settings.clone(),
+                tx.clone(),
+                searcher.clone(),
+                frame.clone()
+            );
+            data.library_loading = true
+        }
+        data.searcher = Some(searcher);
+        data.loader = Some(images::Loader::spawn(
+            data.mpc.settings.clone(),
+            data.paths.0.clone(),
+            &data.config,
+            tx.clone(),
+            frame.clone()
+        ));
+        data.request_covers(frame);
+        data.events = Some((tx, rx));
+        data.rebuild_list()
     }
 
     fn update(&mut self, ctx: &CtxRef, frame: &Frame) {
         let Self { data } = self;
         let input = ctx.input();
 
-        if input.key_pressed(Key::Escape) || input.key_released(Key::Escape) {
-            frame.quit()
-        }
-        if input.key_pressed(Key::Enter) && data.mpc.switch(data.selected_pos as u32).is_ok() {
-            frame.quit()
+        // quit goes on release as well as on press. with the overlay open only the release closes it, so
+        // the same key doesn't also quit, even when the press and release land in the same frame
+        let overlay_open = data.reverse_search.is_some();
+        if data.keymap.released(input, Action::Quit) {
+            match overlay_open {
+                true => data.finish_reverse_search(false),
+                false => frame.quit()
+            }
         }
 
-        if input.modifiers.ctrl && input.key_pressed(Key::U) {
-            data.search_query = String::new();
-            data.selected = data.current_pos;
-            data.selected_pos = data.current_pos;
-            data.need_list_scroll = true;
-            data.interacted = false
+        for action in data.keymap.pressed(input) {
+            match action {
+                Action::MoveUp | Action::MoveDown if !data.list.is_empty() => {
+                    let last = data.list.len() - 1;
+                    let to = match (action, data.selected) {
+                        (Action::MoveUp, 0) => last,
+                        (Action::MoveUp, x) => x - 1,
+                        (_, x) if x == last => 0,
+                        (_, x) => x + 1
+                    };
+                    data.select(to)
+                }
+                Action::PageUp => data.select(data.selected.saturating_sub(data.page_rows)),
+                Action::PageDown => data.select(data.selected + data.page_rows),
+                Action::Top => data.select(0),
+                Action::Bottom => data.select(usize::MAX),
+                Action::Play | Action::Append | Action::PlayNext if data.reverse_search.is_some() => {
+                    data.finish_reverse_search(true)
+                }
+                Action::Play | Action::Append | Action::PlayNext => {
+                    let how = match action {
+                        Action::Append => Add::Append,
+                        Action::PlayNext => Add::Next,
+                        _ => Add::Play
+                    };
+                    match data.activate(data.selected, how) {
+                        Ok(true) => frame.quit(),
+                        Ok(false) => {}
+                        Err(e) => data.error = Some(e.to_string())
+                    }
+                }
+                Action::Clear => match &mut data.reverse_search {
+                    Some(x) => {
+                        x.text = String::new();
+                        data.find_reverse_search(false)
+                    }
+                    None => {
+                        data.search_query = String::new();
+                        data.history_pos = None;
+                        data.rebuild_list()
+                    }
+                },
+                Action::Quit if !overlay_open => frame.quit(),
+                Action::MoveToTop | Action::MoveToBottom | Action::Remove | Action::Crop => {
+                    let edit = match action {
+                        Action::MoveToTop => Edit::Top,
+                        Action::MoveToBottom => Edit::Bottom,
+                        Action::Remove => Edit::Remove,
+                        _ => Edit::Crop
+                    };
+                    if let Err(e) = data.edit_queue(data.selected, edit) {
+                        data.error = Some(e.to_string())
+                    }
+                }
+                Action::ToggleMark => {
+                    data.toggle_mark(data.selected);
+                    data.select(data.selected + 1)
+                }
+                Action::MarkAll => data.mark_all(),
+                Action::TogglePause => data.control(Control::TogglePause),
+                Action::Stop => data.control(Control::Stop),
+                Action::Next => data.control(Control::Next),
+                Action::Previous => data.control(Control::Previous),
+                Action::SeekBack => data.control(Control::Seek(-(data.config.seek_step.unwrap_or(5) as i64))),
+                Action::SeekForward => data.control(Control::Seek(data.config.seek_step.unwrap_or(5) as i64)),
+                Action::VolumeDown => data.control(Control::Volume(-data.config.volume_step.unwrap_or(5))),
+                Action::VolumeUp => data.control(Control::Volume(data.config.volume_step.unwrap_or(5))),
+                Action::ToggleRepeat => data.control(Control::Repeat),
+                Action::ToggleRandom => data.control(Control::Random),
+                Action::ToggleSingle => data.control(Control::Single),
+                Action::ToggleConsume => data.control(Control::Consume),
+                Action::CycleCrossfade => data.control(Control::Crossfade),
+                Action::CycleReplayGain => data.control(Control::ReplayGain),
+                Action::SwitchMode => {
+                    data.mode = match data.mode {
+                        Mode::Queue => Mode::Library,
+                        Mode::Library => Mode::Queue
+                    };
+                    if data.mode == Mode::Library && data.library.is_none() && !data.library_loading {
+                        let tx = data.events.as_ref().unwrap().0.clone();
+                        let searcher = data.searcher.clone().unwrap();
+                        worker::load_library(data.mpc.settings.clone(), tx, searcher, frame.clone());
+                        data.library_loading = true
+                    }
+                    // positions in the old list point into the other mode's songs
+                    data.list = vec![];
+                    data.marked.clear();
+                    data.rebuild_list();
+                    data.showing_info = usize::MAX;
+                    data.error = None;
+                    data.notice = None
+                }
+                Action::ToggleCase => {
+                    data.exact_case = !data.exact_case;
+                    data.notice = match data.exact_case {
+                        true => Some("matching case exactly".to_string()),
+                        false => Some("ignoring case".to_string())
+                    };
+                    data.rebuild_list()
+                }
+                Action::CycleMatching => {
+                    data.matching = data.matching.next();
+                    data.rebuild_list()
+                }
+                Action::HistoryBack if data.reverse_search.is_none() => data.recall_search(true),
+                Action::HistoryForward if data.reverse_search.is_none() => data.recall_search(false),
+                Action::ReverseSearch => data.reverse_search(),
+                _ => {}
+            }
         }
 
         if input.pointer.any_pressed() {
@@ -117,85 +276,77 @@ impl epi::App for Rinse {
             data.need_list_scroll = false
         }
 
-        if !data.list.is_empty() && input.key_pressed(Key::Tab) {
-            match input.modifiers.shift {
-                true => {
-                    if data.selected == 0 {
-                        data.selected = data.list.len() - 1
+        while let Some(Ok(event)) = data.events.as_ref().map(|x| x.1.try_recv()) {
+            match event {
+                Event::Library(library) => {
+                    match library {
+                        Ok(x) => data.library = Some(x),
+                        Err(e) => data.error = Some(["Unable to load the library: ", &e.to_string()].join(""))
                     }
-                    else {
-                        data.selected -= 1
+                    data.library_loading = false;
+                    if data.mode == Mode::Library {
+                        data.rebuild_list();
+                        data.showing_info = usize::MAX
                     }
-                    data.need_list_scroll = true;
-                    data.interacted = true
                 }
-                false => {
-                    if data.selected == data.list.len() - 1 {
-                        data.selected = 0
+                Event::Results(generation, results) => {
+                    data.set_list(generation, results);
+                    data.request_covers(frame)
+                }
+                Event::Queue(changes, len) => {
+                    // from the list, the queue may already have changed under its positions
+                    let selected_id = data.list.get(data.selected).map(|x| x.id);
+
+                    if let Some(x) = &data.searcher {
+                        x.update_queue(changes.clone(), len)
                     }
-                    else {
-                        data.selected += 1
+                    data.queue.truncate(len);
+                    for song in changes {
+                        match song.place.map(|x| x.pos as usize) {
+                            Some(x) if x < data.queue.len() => data.queue[x] = song,
+                            Some(_) => data.queue.push(song),
+                            None => {}
+                        }
+                    }
+                    if data.mode != Mode::Queue {
+                        continue
                     }
-                    data.need_list_scroll = true;
-                    data.interacted = true
+                    data.rebuild_list();
+                    data.list_anchor = selected_id;
+                    data.showing_info = usize::MAX
                 }
+                Event::Status(status) => data.set_status(*status),
+                Event::Cover(file, image) => data.add_cover(file, image, frame)
             }
         }
 
-        if data.update_timer.as_ref().unwrap().elapsed().as_millis() > 33 {
-            if let Ok(status) = data.mpc.status() {
-                let current_pos = status.song.unwrap().pos as usize;
-                let more = match status.nextsong.is_some() {
+        if data.switcher_timer.as_ref().unwrap().elapsed().as_secs() > 4 && !data.hold_switcher {
+            let next = match data.switcher_cycle {
+                0 => 3,
+                1 => 2,
+                2 => 3,
+                3 => match data.selected == data.current_pos {
                     true => 2,
-                    false => 3
-                };
-
-                if current_pos != data.current_pos {
-                    let mut cycle = None;
-                    if !data.interacted {
-                        data.selected = current_pos;
-                        data.selected_pos = current_pos;
-                        cycle = Some(more);
-                    }
-
-                    data.duration = status.duration.map(|x| x.to_owned().num_milliseconds());
-
-                    data.switcher_cycle = cycle.unwrap_or_else(|| match data.selected == current_pos {
-                        true => more,
-                        false if more == 2 => 1,
-                        false => 0
-                    });
-                    data.switcher_timer = Some(Instant::now());
-                    data.switcher = utils::gen_switcher(data.switcher_cycle, &status, &data.queue);
-
-                    data.current_pos = current_pos
-                }
-
-                if data.switcher_timer.as_ref().unwrap().elapsed().as_secs() > 4 {
-                    let next = match data.switcher_cycle {
-                        0 => 3,
-                        1 => 2,
-                        2 => 3,
-                        3 => match data.selected == data.current_pos {
-                            true => 2,
-                            false if more == 2 => 1,
-                            false => 0
-                        },
-                        _ => unreachable!()
-                    };
-                    data.switcher = utils::gen_switcher(next, &status, &data.queue);
-                    data.switcher_cycle = next;
-                    data.switcher_timer = Some(Instant::now())
-                }
-
-                data.state = status.state;
-                data.elapsed = status.elapsed.map(|x| x.to_owned().num_milliseconds());
-            }
-            data.update_timer = Some(Instant::now())
+                    false if data.status.nextsong.is_some() => 1,
+                    false => 0
+                },
+                _ => unreachable!()
+            };
+            data.switcher = utils::gen_switcher(next, &data.status, &data.queue);
+            data.switcher_cycle = next;
+            data.switcher_timer = Some(Instant::now())
+        }
+        // nothing else wakes the ui while paused or stopped
+        let due = data.switcher_timer.unwrap() + Duration::from_secs(5);
+        if data.switcher_wake != Some(due) {
+            worker::repaint_at(due, frame.clone());
+            data.switcher_wake = Some(due)
         }
 
-        if data.showing_info != data.selected_pos {
-            let song = SongInfo::update(&data.paths.0, &data.queue[data.selected_pos]);
+        // an empty list in the library has no song to show, selected_pos is left pointing at the queue
+        let no_result = data.mode == Mode::Library && data.list.is_empty();
+        if data.showing_info != data.selected_pos && data.selected_pos < data.songs().len() && !no_result {
+            let song = SongInfo::update(&data.songs()[data.selected_pos]);
 
             data.info_title = Some(song.title);
             data.info_artist = song.artist;
@@ -203,10 +354,8 @@ impl epi::App for Rinse {
             data.info_duration = song.duration;
             data.info_date = song.date;
 
-            frame.free_texture(data.cover.unwrap().1);
-            let texture = frame.alloc_texture(song.cover);
-            let size = [250.0, 250.0].into();
-            data.cover = Some((size, texture));
+            data.paths.1 = song.filepath;
+            data.request_covers(frame);
 
             data.showing_info = data.selected_pos
         }
@@ -226,7 +375,8 @@ impl epi::App for Rinse {
                 ui.spacing_mut().item_spacing = Vec2::new(0.0, 0.0);
                 ui.spacing_mut().slider_width = 200.0;
                 ui.add_space(20.0);
-                if let Some((size, texture)) = data.cover {
+                let cover = data.covers.get(&data.paths.1).copied().or(data.placeholder);
+                if let Some(texture) = cover {
                     ui.horizontal_top(|ui| {
                         ui.add_space(15.0);
                         match data.selected == data.current_pos {
@@ -237,7 +387,7 @@ impl epi::App for Rinse {
                                 ui.visuals_mut().widgets.noninteractive.bg_stroke.color = data.colours.base0F
                             }
                         }
-                        ui.group(|ui| ui.vertical(|ui| ui.image(texture, size)))
+                        ui.group(|ui| ui.vertical(|ui| ui.image(texture, [250.0, 250.0])))
                     });
                 }
                 ui.add_space(20.0);
@@ -246,7 +396,7 @@ impl epi::App for Rinse {
                     ui.horizontal_wrapped(|ui| {
                         ui.set_max_width(300.0);
                         ui.label(
-                            RichText::new(data.info_title.as_ref().unwrap().to_string())
+                            RichText::new(data.info_title.as_deref().unwrap_or_default())
                                 .heading()
                                 .color(data.colours.base05)
                         )
@@ -331,14 +481,14 @@ impl epi::App for Rinse {
                     });
 
                     ui.add_space(20.0);
-                    let filler = ui.available_height() - 80.0;
+                    let filler = ui.available_height() - 112.0;
                     if filler > 0.0 {
                         ui.add_space(filler)
                     }
                 });
 
-                let elapsed = data.elapsed.unwrap_or(0);
                 let duration = data.duration.unwrap_or(0);
+                let elapsed = data.position().min(duration);
                 let progress_label = utils::progress_string(elapsed, duration);
                 let mut seek_pos = elapsed as f32;
 
@@ -348,6 +498,33 @@ impl epi::App for Rinse {
                     State::Stop => ("", data.colours.base01, data.colours.base01, data.colours.base01)
                 };
 
+                ui.horizontal(|ui| {
+                    ui.add_space(38.0);
+                    ui.spacing_mut().item_spacing = Vec2::new(16.0, 0.0);
+                    let play_icon = match data.state {
+                        State::Play => "",
+                        _ => ""
+                    };
+                    let seek = data.config.seek_step.unwrap_or(5) as i64;
+                    let volume = data.config.volume_step.unwrap_or(5);
+                    let controls = [
+                        ("", Control::Volume(-volume)),
+                        ("", Control::Previous),
+                        ("", Control::Seek(-seek)),
+                        (play_icon, Control::TogglePause),
+                        ("", Control::Stop),
+                        ("", Control::Seek(seek)),
+                        ("", Control::Next),
+                        ("", Control::Volume(volume))
+                    ];
+                    for (icon, control) in controls {
+                        let button = Button::new(RichText::new(icon).monospace().color(data.colours.base04));
+                        if ui.add(button.frame(false)).clicked() {
+                            data.control(control)
+                        }
+                    }
+                });
+                ui.add_space(12.0);
                 ui.vertical_centered(|ui| {
                     ui.label(RichText::new(progress_label).small().color(progress_colour))
                 });
@@ -375,7 +552,7 @@ impl epi::App for Rinse {
                         let seek =
                             ui.add(Slider::new(&mut seek_pos, 0.0..=duration as f32).show_value(false));
                         if seek.clicked() || seek.drag_released() {
-                            data.mpc.rewind((seek_pos / 1000.0).floor() as i64).unwrap()
+                            data.control(Control::SeekTo((seek_pos / 1000.0).floor() as i64))
                         }
                     }
                     else {
@@ -393,8 +570,44 @@ impl epi::App for Rinse {
 
                 ui.vertical(|ui| {
                     ui.add_space(3.0);
-                    if data.switcher_cycle == 3 {
-                        ui.label(RichText::new(&data.switcher).small().color(data.colours.base03))
+                    if let Some(x) = &data.error {
+                        ui.label(
+                            RichText::new(utils::ellipsize(x, 46))
+                                .small()
+                                .color(data.colours.base08)
+                        )
+                    }
+                    else if let Some(x) = &data.notice {
+                        ui.label(
+                            RichText::new(utils::ellipsize(x, 46))
+                                .small()
+                                .color(data.colours.base0E)
+                        )
+                    }
+                    else if data.switcher_cycle == 3 {
+                        let options = ui.horizontal(|ui| {
+                            ui.spacing_mut().item_spacing = Vec2::new(12.0, 0.0);
+                            for (text, on, control) in utils::gen_options(&data.status) {
+                                let colour = match on {
+                                    true => data.colours.base04,
+                                    false => data.colours.base02
+                                };
+                                let text = RichText::new(text).small().color(colour);
+                                match control {
+                                    Some(x) => {
+                                        if ui.add(Button::new(text).frame(false)).clicked() {
+                                            data.control(x)
+                                        }
+                                    }
+                                    None => {
+                                        ui.label(text);
+                                    }
+                                }
+                            }
+                        });
+                        // don't rotate away from it while it's being clicked on
+                        data.hold_switcher = options.response.hovered();
+                        options.response
                     }
                     else {
                         match data.switcher.len() > 47 {
@@ -421,6 +634,8 @@ impl epi::App for Rinse {
                 ui.add_space(2.0)
             });
 
+        let mut double_clicked = None;
+        let mut mark_clicked = None;
         CentralPanel::default()
             .frame(eframe::egui::containers::Frame {
                 margin: Vec2::new(8.0, 8.0),
@@ -441,75 +656,116 @@ impl epi::App for Rinse {
                         true => {
                             ui.vertical_centered_justified(|ui| {
                                 ui.add_space(30.0);
-                                ui.label(
-                                    RichText::new("¯\\_(ツ)_/¯")
-                                        .text_style(TextStyle::Button)
-                                        .color(data.colours.base08)
-                                );
+                                if let Some(x) = &data.search_error {
+                                    ui.label(RichText::new(x).monospace().color(data.colours.base08));
+                                }
+                                else if data.mode == Mode::Library && data.library_loading {
+                                    ui.label(
+                                        RichText::new("loading library…")
+                                            .heading()
+                                            .color(data.colours.base03)
+                                    );
+                                }
+                                else if !data.searching {
+                                    ui.label(
+                                        RichText::new("¯\\_(ツ)_/¯")
+                                            .text_style(TextStyle::Button)
+                                            .color(data.colours.base08)
+                                    );
+                                }
                                 ui.add_space(ui.available_height() - 42.0)
                             });
                             data.selected = 0;
                             data.selected_pos = data.current_pos
                         }
                         false => {
-                            ScrollArea::vertical()
+                            if let Some(x) = data.list.get(data.selected) {
+                                data.selected_pos = x.pos
+                            }
+
+                            // only the rows in view get laid out, so the selection can't scroll_to_me when
+                            // it's off screen and has to be scrolled to by offset instead
+                            let row_height = (ui.fonts().row_height(TextStyle::Monospace)
+                                + ui.spacing().button_padding.y * 2.0)
+                                .max(ui.spacing().interact_size.y);
+                            let list_height = ui.available_height() - 42.0;
+                            data.page_rows =
+                                ((list_height / (row_height + ui.spacing().item_spacing.y)) as usize).max(1);
+                            let mut scroll = ScrollArea::vertical()
                                 .auto_shrink([false; 2])
-                                .max_height(ui.available_height() - 42.0)
-                                .show(ui, |ui| {
-                                    ui.with_layout(Layout::top_down_justified(Align::Min), |ui| {
-                                        let selected_bg = match data.selected == data.current_pos {
-                                            true => data.colours.base09,
-                                            false => data.colours.base0F
+                                .max_height(list_height);
+                            if data.need_list_scroll {
+                                let offset = data.selected as f32
+                                    * (row_height + ui.spacing().item_spacing.y)
+                                    - (list_height - row_height) / 2.0;
+                                scroll = scroll.vertical_scroll_offset(offset.max(0.0));
+                                if data.interacted {
+                                    data.need_list_scroll = false
+                                }
+                            }
+                            scroll.show_rows(ui, row_height, data.list.len(), |ui, rows| {
+                                ui.with_layout(Layout::top_down_justified(Align::Min), |ui| {
+                                    let selected_bg = match data.selected == data.current_pos {
+                                        true => data.colours.base09,
+                                        false => data.colours.base0F
+                                    };
+                                    ui.visuals_mut().selection = Selection {
+                                        bg_fill: selected_bg,
+                                        stroke:  Stroke {
+                                            width: 0.0,
+                                            color: data.colours.base00
+                                        }
+                                    };
+                                    ui.visuals_mut().widgets.active.bg_fill = data.colours.base01;
+                                    ui.visuals_mut().widgets.hovered.bg_fill = data.colours.base01;
+                                    ui.visuals_mut().widgets.active.bg_stroke = Stroke {
+                                        width: 1.2,
+                                        color: data.colours.base09
+                                    };
+                                    ui.visuals_mut().widgets.hovered.bg_stroke = Stroke {
+                                        width: 1.0,
+                                        color: data.colours.base0F
+                                    };
+                                    for (i, song) in rows.map(|x| (x, &data.list[x])) {
+                                        let (highlight_colour, dim_colour) = match i == data.selected {
+                                            true => (data.colours.base06, data.colours.base02),
+                                            false => (data.colours.base0D, data.colours.base03)
                                         };
-                                        ui.visuals_mut().selection = Selection {
-                                            bg_fill: selected_bg,
-                                            stroke:  Stroke {
-                                                width: 0.0,
-                                                color: data.colours.base00
+                                        let text_colour = match i == data.selected {
+                                            true => data.colours.base00,
+                                            false
+                                                if data.mode == Mode::Queue
+                                                    && song.pos == data.current_pos =>
+                                            {
+                                                data.colours.base09
                                             }
+                                            false => data.colours.base04
                                         };
-                                        ui.visuals_mut().widgets.active.bg_fill = data.colours.base01;
-                                        ui.visuals_mut().widgets.hovered.bg_fill = data.colours.base01;
-                                        ui.visuals_mut().widgets.active.bg_stroke = Stroke {
-                                            width: 1.2,
-                                            color: data.colours.base09
-                                        };
-                                        ui.visuals_mut().widgets.hovered.bg_stroke = Stroke {
-                                            width: 1.0,
-                                            color: data.colours.base0F
-                                        };
-                                        for (i, song) in data.list.iter().enumerate() {
-                                            let text_colour = match i == data.selected {
-                                                true => data.colours.base00,
-                                                false if i == data.current_pos => data.colours.base09,
-                                                false => data.colours.base04
-                                            };
-                                            let entry = ui.selectable_label(
-                                                i == data.selected,
-                                                RichText::new(&song.title).monospace().color(text_colour)
-                                            );
-                                            if i == data.selected {
-                                                data.selected_pos = song.pos;
-                                                if data.need_list_scroll {
-                                                    entry.scroll_to_me(Align::Center);
-                                                    if data.interacted {
-                                                        data.need_list_scroll = false
-                                                    }
-                                                }
-                                            }
-                                            if entry.clicked() {
-                                                data.selected = i;
-                                                data.selected_pos = song.pos;
-                                                data.interacted = true
-                                            }
-                                            if entry.double_clicked()
-                                                && data.mpc.switch(song.pos as u32).is_ok()
-                                            {
-                                                frame.quit()
+                                        let entry = ui.selectable_label(
+                                            i == data.selected,
+                                            utils::gen_row(
+                                                song,
+                                                data.is_marked(song),
+                                                text_colour,
+                                                highlight_colour,
+                                                dim_colour
+                                            )
+                                        );
+                                        if entry.clicked() {
+                                            let x = ui.input().modifiers;
+                                            if x.shift || x.ctrl {
+                                                mark_clicked = Some((data.selected, i, x.shift))
                                             }
+                                            data.selected = i;
+                                            data.selected_pos = song.pos;
+                                            data.interacted = true
                                         }
-                                    })
-                                });
+                                        if entry.double_clicked() {
+                                            double_clicked = Some(i)
+                                        }
+                                    }
+                                })
+                            });
                         }
                     }
                     ui.separator();
@@ -518,7 +774,24 @@ impl epi::App for Rinse {
                     ui.horizontal_top(|ui| {
                         ui.spacing_mut().item_spacing = Vec2::new(0.0, 0.0);
                         ui.add_space(4.0);
-                        ui.label(RichText::new("").heading().color(data.colours.base0F));
+                        let (icon, icon_colour, query_colour) = match (data.mode, &data.search_error) {
+                            (_, Some(_)) => ("", data.colours.base08, data.colours.base08),
+                            (Mode::Queue, None) => ("", data.colours.base0F, data.colours.base05),
+                            (Mode::Library, None) => ("", data.colours.base0F, data.colours.base05)
+                        };
+                        ui.label(RichText::new(icon).heading().color(icon_colour));
+                        ui.add_space(6.0);
+                        let matching = match (data.matching, data.config.scorer) {
+                            (Matching::Fuzzy, Scorer::Fuzzy) => "fuzzy",
+                            (Matching::Fuzzy, Scorer::Classic) => "classic",
+                            (Matching::Exact, _) => "exact",
+                            (Matching::Regex, _) => "regex"
+                        };
+                        let matching = match data.marked.len() {
+                            0 => matching.to_string(),
+                            x => [matching, " · ", &x.to_string(), " marked"].join("")
+                        };
+                        ui.label(RichText::new(matching).small().color(data.colours.base03));
                         ui.add_space(10.0);
                         ui.vertical_centered_justified(|ui| {
                             ui.set_max_width(330.0);
@@ -530,23 +803,46 @@ impl epi::App for Rinse {
                                 }
                             };
 
-                            let search = ui.add(
-                                TextEdit::singleline(&mut data.search_query)
-                                    .frame(false)
-                                    .text_color(data.colours.base05)
-                            );
+                            // the overlay takes over the search bar while it's open
+                            let text = match &mut data.reverse_search {
+                                Some(x) => &mut x.text,
+                                None => &mut data.search_query
+                            };
+                            let len = text.chars().count();
+                            let before = text.clone();
+                            let search =
+                                ui.add(TextEdit::singleline(text).frame(false).text_color(query_colour));
                             search.request_focus();
 
-                            if search.changed() {
-                                data.list = search::build_list(&data.search_query, &data.queue);
-                                if !data.search_query.is_empty() {
-                                    data.selected = 0;
-                                    data.interacted = true
+                            // alt+letter is a key binding but x11 still types the letter
+                            let modifiers = ui.input().modifiers;
+                            let changed = match search.changed() && modifiers.alt && !modifiers.ctrl {
+                                true => {
+                                    *text = before;
+                                    false
                                 }
-                                else {
-                                    data.selected = data.current_pos;
-                                    data.need_list_scroll = true;
-                                    data.interacted = false
+                                false => search.changed()
+                            };
+
+                            // up and down also move the cursor, so this has to come after the TextEdit has
+                            // had them
+                            if data.query_recalled {
+                                if let Some(mut state) = TextEditState::load(ui.ctx(), search.id) {
+                                    state.set_ccursor_range(Some(CCursorRange::one(CCursor::new(len))));
+                                    state.store(ui.ctx(), search.id)
+                                }
+                                data.query_recalled = false
+                            }
+
+                            if changed {
+                                data.error = None;
+                                data.notice = None;
+                                match data.reverse_search.is_some() {
+                                    true => data.find_reverse_search(false),
+                                    false => {
+                                        data.history_pos = None;
+                                        data.rebuild_list()
+                                    }
                                 }
                             }
                         })
@@ -554,6 +850,58 @@ impl epi::App for Rinse {
                 })
             });
 
-        frame.request_repaint()
+        if data.reverse_search.is_some() {
+            Area::new("reverse_search")
+                .anchor(Align2::LEFT_BOTTOM, [12.0, -50.0])
+                .show(ctx, |ui| {
+                    eframe::egui::containers::Frame {
+                        margin: Vec2::new(10.0, 8.0),
+                        corner_radius: 0.0,
+                        fill: data.colours.base01,
+                        stroke: Stroke {
+                            width: 1.0,
+                            color: data.colours.base0F
+                        },
+                        ..Default::default()
+                    }
+                    .show(ui, |ui| {
+                        ui.set_width(360.0);
+                        let found = data.reverse_search.as_ref().and_then(|x| x.found);
+                        let matches = data.reverse_matches();
+                        // the newest few, or the few around the one being previewed
+                        let skip = found
+                            .and_then(|x| matches.iter().position(|y| *y == x))
+                            .unwrap_or(0)
+                            .saturating_sub(7);
+                        for i in matches.into_iter().skip(skip).take(8).rev() {
+                            let colour = match Some(i) == found {
+                                true => data.colours.base09,
+                                false => data.colours.base04
+                            };
+                            ui.label(RichText::new(&data.search_history[i]).monospace().color(colour));
+                        }
+                        let hint = match data.search_history.is_empty() {
+                            true => "no past searches yet",
+                            false => "search history · ctrl+r older · enter keep · esc cancel"
+                        };
+                        ui.label(RichText::new(hint).small().color(data.colours.base03))
+                    })
+                });
+        }
+
+        if let Some((from, to, range)) = mark_clicked {
+            match range {
+                true => data.mark_range(from, to),
+                false => data.toggle_mark(to)
+            }
+        }
+
+        if let Some(i) = double_clicked {
+            match data.activate(i, Add::Play) {
+                Ok(true) => frame.quit(),
+                Ok(false) => {}
+                Err(e) => data.error = Some(e.to_string())
+            }
+        }
     }
 }
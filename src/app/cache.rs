@@ -0,0 +1,165 @@
+use std::{
+    env::var,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use anyhow::Result;
+use eframe::epi::Image;
+use image::{codecs::jpeg::JpegEncoder, ColorType, DynamicImage, ImageFormat, RgbaImage};
+
+// a 250x250 thumbnail comes to around 20KB rather than the 250KB of raw pixels
+const QUALITY: u8 = 90;
+
+// older versions kept raw pixels straight in the cache directory, next to the library cache
+const RAW_SIZE: u64 = 250 * 250 * 4;
+
+// bytes of covers as of the last evict plus whatever has been put since, None until the first evict
+static TOTAL: Mutex<Option<u64>> = Mutex::new(None);
+
+// also where the library cache goes, see library::load
+pub fn root() -> PathBuf {
+    let prefix = var("XDG_CACHE_HOME").unwrap_or_else(|_| [&var("HOME").unwrap(), ".cache"].join("/"));
+    Path::new(&[&prefix, "rinse"].join("/")).to_owned()
+}
+
+fn dir() -> PathBuf { root().join("covers") }
+
+// fnv-1a, the std hasher isn't guaranteed to stay the same between rust versions
+fn hash(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |x, y| {
+        (x ^ *y as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+// a file's path along with its size and when it was modified, so a changed file gets a new thumbnail
+pub fn file_key(path: &Path) -> Result<String> {
+    let meta = fs::metadata(path)?;
+    let modified = meta.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+    Ok([
+        &path.to_string_lossy(),
+        meta.len().to_string().as_str(),
+        &modified.to_string()
+    ]
+    .join(":"))
+}
+
+fn entry(key: &str) -> PathBuf { dir().join([&hash(key.as_bytes()), ".jpg"].join("")) }
+
+// an empty file in place of the thumbnail for songs without a cover
+fn miss(key: &str) -> PathBuf { entry(key).with_extension("none") }
+
+pub fn is_miss(key: &str) -> bool { miss(key).is_file() }
+
+pub fn put_miss(key: &str) -> Result<()> {
+    fs::create_dir_all(dir())?;
+    Ok(fs::write(miss(key), "")?)
+}
+
+// a hit counts as a use, eviction goes by modification time
+pub fn get(key: &str) -> Option<Image> {
+    let path = entry(key);
+    let image = image::load_from_memory_with_format(&fs::read(&path).ok()?, ImageFormat::Jpeg)
+        .ok()?
+        .to_rgba8();
+    if let Ok(x) = File::options().write(true).open(&path) {
+        let _ = x.set_modified(SystemTime::now());
+    }
+    let size = [image.width() as usize, image.height() as usize];
+    Some(Image::from_rgba_unmultiplied(size, &image))
+}
+
+// limit is in bytes
+pub fn put(key: &str, thumbnail: &RgbaImage, limit: u64) -> Result<()> {
+    fs::create_dir_all(dir())?;
+    let rgb = DynamicImage::ImageRgba8(thumbnail.to_owned()).to_rgb8();
+    let mut buffer = vec![];
+    JpegEncoder::new_with_quality(&mut buffer, QUALITY).encode(
+        &rgb,
+        rgb.width(),
+        rgb.height(),
+        ColorType::Rgb8
+    )?;
+    // written somewhere else first so another worker never reads half of it
+    let path = entry(key);
+    let part = path.with_extension("part");
+    let replaced = fs::metadata(&path).map(|x| x.len()).unwrap_or(0);
+    fs::write(&part, &buffer)?;
+    fs::rename(part, path)?;
+
+    let over = {
+        let mut total = TOTAL.lock().unwrap();
+        *total = total.map(|x| (x + buffer.len() as u64).saturating_sub(replaced));
+        total.map(|x| x > limit).unwrap_or(true)
+    };
+    match over {
+        true => evict(limit),
+        false => Ok(())
+    }
+}
+
+// once there's more than limit the least recently used go first, down to a tenth under it so the next few
+// puts don't have to go through the whole directory again
+pub fn evict(limit: u64) -> Result<()> {
+    let mut entries = vec![];
+    for x in fs::read_dir(dir())?.flatten() {
+        let meta = x.metadata()?;
+        if matches!(
+            x.path().extension().and_then(|y| y.to_str()),
+            Some("jpg" | "none")
+        ) {
+            entries.push((meta.modified()?, meta.len(), x.path()))
+        }
+    }
+    entries.sort();
+
+    let mut total = entries.iter().map(|x| x.1).sum::<u64>();
+    if total > limit {
+        for (_, size, path) in entries {
+            if total <= limit / 10 * 9 {
+                break
+            }
+            if fs::remove_file(path).is_ok() {
+                total -= size
+            }
+        }
+    }
+    *TOTAL.lock().unwrap() = Some(total);
+    Ok(())
+}
+
+// thumbnails from older versions are never read again
+pub fn remove_raw() {
+    for x in fs::read_dir(root()).into_iter().flatten().flatten() {
+        let raw = x
+            .metadata()
+            .map(|y| y.is_file() && y.len() == RAW_SIZE)
+            .unwrap_or(false);
+        if raw && x.file_name() != "library" {
+            let _ = fs::remove_file(x.path());
+        }
+    }
+}
+
+pub fn clear() -> Result<()> {
+    remove_raw();
+    match dir().is_dir() {
+        true => Ok(fs::remove_dir_all(dir())?),
+        false => Ok(())
+    }
+}
+
+pub fn stats() -> String {
+    let sizes = fs::read_dir(dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|x| x.path().extension().and_then(|y| y.to_str()) == Some("jpg"))
+        .filter_map(|x| x.metadata().ok().map(|y| y.len()))
+        .collect::<Vec<_>>();
+    let total = sizes.iter().sum::<u64>() as f64 / 1024.0 / 1024.0;
+    format!("{} covers, {:.1}MB in {}", sizes.len(), total, dir().display())
+}
@@ -0,0 +1,78 @@
+use std::env::args;
+
+use anyhow::{bail, Result};
+
+use super::{cache, history, search::Scorer};
+
+const USAGE: &str = "usage: rinse [options]
+
+options:
+    --host <host>          mpd host, socket path or password@host (overrides MPD_HOST)
+    --port <port>          mpd port (overrides MPD_PORT)
+    --password <password>  mpd password
+    --scorer <scorer>      fuzzy (default) or classic search ranking
+    --export-history       print the play history used for ranking and exit
+    --clear-history        forget the play history and exit
+    --cache-stats          print how many covers are cached and how much space they take, then exit
+    --clear-cache          delete the cached covers and exit
+    -h, --help             show this message";
+
+#[derive(Default)]
+pub struct Args {
+    pub host:     Option<String>,
+    pub port:     Option<u16>,
+    pub password: Option<String>,
+    pub scorer:   Option<Scorer>
+}
+
+pub fn parse() -> Result<Args> {
+    let mut parsed = Args::default();
+    let mut args = args().skip(1);
+
+    while let Some(x) = args.next() {
+        match x.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0)
+            }
+            "--export-history" => {
+                print!("{}", history::export());
+                std::process::exit(0)
+            }
+            "--clear-history" => {
+                history::clear()?;
+                println!("play history cleared");
+                std::process::exit(0)
+            }
+            "--cache-stats" => {
+                println!("{}", cache::stats());
+                std::process::exit(0)
+            }
+            "--clear-cache" => {
+                cache::clear()?;
+                println!("cover cache cleared");
+                std::process::exit(0)
+            }
+            "--host" => parsed.host = Some(value(&x, args.next())?),
+            "--port" => match value(&x, args.next())?.parse::<u16>() {
+                Ok(y) => parsed.port = Some(y),
+                Err(_) => bail!("--port needs a number between 0 and 65535")
+            },
+            "--password" => parsed.password = Some(value(&x, args.next())?),
+            "--scorer" => match value(&x, args.next())?.as_str() {
+                "fuzzy" => parsed.scorer = Some(Scorer::Fuzzy),
+                "classic" => parsed.scorer = Some(Scorer::Classic),
+                _ => bail!("--scorer needs to be fuzzy or classic")
+            },
+            _ => bail!("Unknown argument '{}'\n\n{}", x, USAGE)
+        }
+    }
+    Ok(parsed)
+}
+
+fn value(flag: &str, next: Option<String>) -> Result<String> {
+    match next {
+        Some(x) => Ok(x),
+        None => bail!("{} needs a value", flag)
+    }
+}
@@ -0,0 +1,52 @@
+use std::{env::var, fs, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::{
+    keys::Keys,
+    search::{Scorer, Weights}
+};
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub host:          Option<String>,
+    pub port:          Option<u16>,
+    pub password:      Option<String>,
+    pub scorer:        Scorer,
+    // match case exactly from the start, alt+c toggles it
+    pub exact_case:    bool,
+    // also match non latin scripts by their ascii transliteration, e.g. "kino" finds "Кино"
+    pub transliterate: bool,
+    pub weights:       Weights,
+    // seconds skipped by the seek keys and buttons, 5 when unset
+    pub seek_step:     Option<u32>,
+    // percent the volume keys and buttons change it by, 5 when unset
+    pub volume_step:   Option<i8>,
+    // cover file names to look for in order, see images::COVERS for the default
+    pub covers:        Option<Vec<String>>,
+    // megabytes of cover thumbnails kept around, 50 when unset
+    pub cache_size:    Option<u64>,
+    pub keys:          Keys
+}
+
+impl Config {
+    // a missing config file is fine, everything has a default
+    pub fn load() -> Result<Self> {
+        let prefix = var("XDG_CONFIG_HOME").unwrap_or_else(|_| [&var("HOME").unwrap(), ".config"].join("/"));
+        let config_path = Path::new(&[&prefix, "rinse", "config.yaml"].join("/")).to_owned();
+        if !config_path.exists() {
+            return Ok(Self::default())
+        }
+
+        let config = fs::read_to_string(&config_path)?;
+        let config = serde_yaml::from_str::<Self>(&config)
+            .with_context(|| format!("Invalid config file {}", config_path.display()))?;
+        config
+            .keys
+            .check()
+            .with_context(|| format!("Invalid config file {}", config_path.display()))?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    env::var,
+    io::{Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    time::Instant
+};
+
+use anyhow::{bail, Result};
+use mpd::Client;
+
+use super::{cli::Args, config::Config, utils};
+
+// the mpd crate is generic over the socket, boxing it lets the rest of the app not care which one we got
+pub trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+pub type Mpc = Client<Box<dyn Stream>>;
+
+#[derive(Clone)]
+pub enum Address {
+    Tcp(String, u16),
+    Unix(PathBuf),
+    Abstract(String)
+}
+
+impl Address {
+    pub fn is_local(&self) -> bool { !matches!(self, Self::Tcp(..)) }
+}
+
+#[derive(Clone)]
+pub struct Settings {
+    pub address:  Address,
+    pub password: Option<String>
+}
+
+impl Settings {
+    // command line beats the environment beats the config file beats whatever we can dig out of mpd.conf
+    pub fn load(args: &Args, config: &Config) -> Result<Self> {
+        let (env_password, env_host) = match var("MPD_HOST") {
+            Ok(x) if !x.is_empty() => split_host(&x),
+            _ => (None, None)
+        };
+        let (arg_password, arg_host) = match &args.host {
+            Some(x) => split_host(x),
+            None => (None, None)
+        };
+        let env_port = match var("MPD_PORT") {
+            Ok(x) => match x.parse::<u16>() {
+                Ok(y) => Some(y),
+                Err(_) => bail!("MPD_PORT is not a valid port: {}", x)
+            },
+            Err(_) => None
+        };
+
+        let host = arg_host.or(env_host).or_else(|| config.host.clone());
+        let port = args.port.or(env_port).or(config.port);
+        let password = args
+            .password
+            .clone()
+            .or(arg_password)
+            .or(env_password)
+            .or_else(|| config.password.clone());
+
+        let address = match host {
+            Some(x) if x.starts_with('@') => Address::Abstract(x[1..].to_owned()),
+            Some(x) if x.starts_with('/') || x.starts_with('~') => {
+                Address::Unix(utils::expand_tilde(&x).into())
+            }
+            Some(x) => Address::Tcp(x, port.unwrap_or(6600)),
+            None => match (find_address(), port) {
+                (Address::Tcp(x, _), Some(y)) => Address::Tcp(x, y),
+                (x, _) => x
+            }
+        };
+
+        Ok(Self { address, password })
+    }
+}
+
+// mpd drops clients that stay quiet for longer than its connection_timeout (60 seconds by default) and we
+// no longer poll, so make sure the connection is still alive before using it. it can also go at any other
+// time (mpd restarting, the network), which only shows when a command fails
+pub struct Connection {
+    pub settings: Settings,
+    mpc:          Mpc,
+    last_used:    Instant
+}
+
+impl Connection {
+    pub fn new(settings: Settings) -> Result<Self> {
+        let mpc = connect(&settings)?;
+        Ok(Self {
+            settings,
+            mpc,
+            last_used: Instant::now()
+        })
+    }
+
+    pub fn run<T>(&mut self, mut f: impl FnMut(&mut Mpc) -> mpd::error::Result<T>) -> Result<T> {
+        if self.last_used.elapsed().as_secs() > 30 && self.mpc.ping().is_err() {
+            self.mpc = connect(&self.settings)?
+        }
+        self.last_used = Instant::now();
+        match f(&mut self.mpc) {
+            // one more go on a new connection, mpd saying no to the command is left alone
+            Err(_) if self.mpc.ping().is_err() => {
+                self.mpc = connect(&self.settings)?;
+                Ok(f(&mut self.mpc)?)
+            }
+            x => Ok(x?)
+        }
+    }
+}
+
+// "password@host", where host may itself be "@abstract_socket"
+fn split_host(s: &str) -> (Option<String>, Option<String>) {
+    match s.split_once('@') {
+        Some((password, host)) if !password.is_empty() => (Some(password.to_owned()), Some(host.to_owned())),
+        _ => (None, Some(s.to_owned()))
+    }
+}
+
+pub fn connect(settings: &Settings) -> Result<Mpc> {
+    let mut client = Client::new(open(&settings.address)?)?;
+    if let Some(x) = &settings.password {
+        client.login(x)?
+    }
+    Ok(client)
+}
+
+pub fn open(address: &Address) -> Result<Box<dyn Stream>> {
+    Ok(match address {
+        Address::Tcp(host, port) => Box::new(TcpStream::connect((host.as_str(), *port))?),
+        Address::Unix(path) => Box::new(UnixStream::connect(path)?),
+        Address::Abstract(name) => Box::new(connect_abstract(name)?)
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn connect_abstract(name: &str) -> Result<UnixStream> {
+    use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+    let address = SocketAddr::from_abstract_name(name.as_bytes())?;
+    Ok(UnixStream::connect_addr(&address)?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn connect_abstract(_name: &str) -> Result<UnixStream> {
+    bail!("Abstract sockets are only supported on linux!")
+}
+
+// sockets listed in mpd.conf win, then the usual runtime dir socket, then whatever tcp address mpd.conf
+// binds to and finally the mpd default
+pub fn find_address() -> Address {
+    let binds = utils::mpd_conf_values("bind_to_address").unwrap_or_default();
+    let port = utils::mpd_conf_values("port")
+        .ok()
+        .and_then(|x| x.first().and_then(|y| y.parse::<u16>().ok()))
+        .unwrap_or(6600);
+
+    for x in &binds {
+        if x.starts_with('/') || x.starts_with('~') {
+            let path = PathBuf::from(utils::expand_tilde(x));
+            if path.exists() {
+                return Address::Unix(path)
+            }
+        }
+    }
+
+    if let Ok(x) = var("XDG_RUNTIME_DIR") {
+        let path = Path::new(&[&x, "mpd", "socket"].join("/")).to_owned();
+        if path.exists() {
+            return Address::Unix(path)
+        }
+    }
+
+    for x in binds {
+        if let Some(y) = x.strip_prefix('@') {
+            return Address::Abstract(y.to_owned())
+        }
+        if !x.starts_with('/') && !x.starts_with('~') {
+            let host = match x.as_str() {
+                "any" | "0.0.0.0" => "127.0.0.1".to_string(),
+                "::" => "::1".to_string(),
+                _ => x
+            };
+            return Address::Tcp(host, port)
+        }
+    }
+
+    Address::Tcp("127.0.0.1".to_string(), port)
+}
@@ -0,0 +1,98 @@
+use std::{
+    collections::HashMap,
+    env::var,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use anyhow::Result;
+use time::Timespec;
+
+// a play counts for half as much every 30 days
+const HALF_LIFE: f64 = 30.0 * 24.0 * 60.0 * 60.0;
+
+// "history" has one "<unix time>\t<file>" line per song played through rinse, "searches" one line per
+// search that something was picked from
+fn path(name: &str) -> PathBuf {
+    let prefix = var("XDG_DATA_HOME").unwrap_or_else(|_| [&var("HOME").unwrap(), ".local/share"].join("/"));
+    Path::new(&[&prefix, "rinse", name].join("/")).to_owned()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or(0)
+}
+
+fn plays() -> Vec<(u64, String)> {
+    fs::read_to_string(path("history"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|x| x.split_once('\t'))
+        .filter_map(|(time, file)| Some((time.parse().ok()?, file.to_owned())))
+        .collect()
+}
+
+pub fn record(file: &str) -> Result<()> {
+    let path = path("history");
+    if let Some(x) = path.parent() {
+        fs::create_dir_all(x)?
+    }
+    let mut history = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(history, "{}\t{}", now(), file)?;
+    Ok(())
+}
+
+// how often and how recently each file was played, a play just now is worth 1
+pub fn frecency() -> HashMap<String, f64> {
+    let now = now();
+    let mut scores = HashMap::new();
+    for (time, file) in plays() {
+        let age = now.saturating_sub(time) as f64;
+        *scores.entry(file).or_insert(0.0) += 0.5_f64.powf(age / HALF_LIFE)
+    }
+    scores
+}
+
+pub fn clear() -> Result<()> {
+    let path = path("history");
+    if path.exists() {
+        fs::remove_file(path)?
+    }
+    Ok(())
+}
+
+// the same lines with readable times, oldest first
+pub fn export() -> String {
+    plays()
+        .into_iter()
+        .map(|(time, file)| {
+            let at = time::at_utc(Timespec::new(time as i64, 0)).rfc3339().to_string();
+            [&at, "\t", &file, "\n"].join("")
+        })
+        .collect()
+}
+
+// oldest first
+pub fn searches() -> Vec<String> {
+    fs::read_to_string(path("searches"))
+        .unwrap_or_default()
+        .lines()
+        .map(|x| x.to_owned())
+        .collect()
+}
+
+pub fn save_searches(searches: &[String]) -> Result<()> {
+    let path = path("searches");
+    if let Some(x) = path.parent() {
+        fs::create_dir_all(x)?
+    }
+    fs::write(
+        path,
+        searches.iter().map(|x| [x, "\n"].join("")).collect::<String>()
+    )?;
+    Ok(())
+}
@@ -1,56 +1,272 @@
 use std::{
-    env::var,
-    fs::{self, read_dir},
-    path::{Path, PathBuf}
+    collections::HashSet,
+    fs::read_dir,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Condvar, Mutex, OnceLock},
+    thread
 };
 
 use anyhow::{bail, Result};
-use eframe::epi::Image;
+use eframe::epi::{Frame, Image};
+use image::io::Reader;
+use regex::Regex;
+
+use super::{cache, config::Config, conn::Settings, proto::Raw, worker::Event};
+
+mod embedded;
 
 const NO_ART: &[u8; 250000] = include_bytes!("../../assets/NO_ART");
 
-pub fn get_cover(paths: &(&str, &str)) -> Image {
-    gen_image(paths).unwrap_or_else(|_| Image::from_rgba_unmultiplied([250, 250], NO_ART))
+// cover file names tried in order when config.yaml doesn't have its own, relative to the song's directory
+const COVERS: &[&str] = &[
+    "cover.*",
+    "folder.*",
+    "front.*",
+    "albumart*",
+    "scans/front.*",
+    "../cover.*",
+    "../folder.*",
+    "../front.*",
+    "../scans/front.*"
+];
+
+// what a cover file can be, the rest of a pattern's matches are ignored
+const EXTENSIONS: &[&str] = &["jpeg", "jpg", "png", "webp", "gif", "bmp"];
+
+// rows either side of the selection whose covers get loaded before they're needed
+pub const PREFETCH: usize = 2;
+
+// CD1, Disc 2, ...
+static DISC: OnceLock<Regex> = OnceLock::new();
+
+struct Jobs {
+    // nearest the selection first
+    waiting: Vec<String>,
+    loading: HashSet<String>
+}
+
+// decoding and resizing a big scan freezes the ui for a moment, so a few workers do it instead. each want
+// replaces whatever is still waiting, so tabbing quickly through the list doesn't build up a backlog
+#[derive(Clone)]
+pub struct Loader {
+    jobs: Arc<(Mutex<Jobs>, Condvar)>
 }
 
-fn gen_image(paths: &(&str, &str)) -> Result<Image> {
-    let image_path = search(paths)?;
+impl Loader {
+    pub fn spawn(
+        settings: Settings, music_dir: String, config: &Config, tx: Sender<Event>, frame: Frame
+    ) -> Self {
+        let covers = config.covers.to_owned();
+        let limit = config.cache_size.unwrap_or(50) * 1024 * 1024;
+        // the limit might have come down since last time
+        thread::spawn(move || {
+            cache::remove_raw();
+            let _ = cache::evict(limit);
+        });
 
-    let cache_path = var("XDG_CACHE_HOME").unwrap_or_else(|_| [&var("HOME").unwrap(), ".cache"].join("/"));
-    let cache_dir = Path::new(&[&cache_path, "rinse"].join("/")).to_owned();
-    if !Path::is_dir(&cache_dir) {
-        fs::create_dir(&cache_dir)?
+        let jobs = Arc::new((
+            Mutex::new(Jobs {
+                waiting: vec![],
+                loading: HashSet::new()
+            }),
+            Condvar::new()
+        ));
+        let workers = thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(1)
+            .min(4);
+        for _ in 0..workers {
+            let jobs = jobs.clone();
+            let (settings, music_dir, covers) = (settings.clone(), music_dir.clone(), covers.clone());
+            let (tx, frame) = (tx.clone(), frame.clone());
+            thread::spawn(move || loop {
+                let (lock, ready) = &*jobs;
+                let file = {
+                    let mut jobs = lock.lock().unwrap();
+                    while jobs.waiting.is_empty() {
+                        jobs = ready.wait(jobs).unwrap()
+                    }
+                    let file = jobs.waiting.remove(0);
+                    jobs.loading.insert(file.to_owned());
+                    file
+                };
+                let image = get_cover(&settings, &music_dir, &file, covers.as_deref(), limit);
+                lock.lock().unwrap().loading.remove(&file);
+                if tx.send(Event::Cover(file, image)).is_err() {
+                    return
+                }
+                frame.request_repaint()
+            });
+        }
+        Self { jobs }
+    }
+
+    // each comes back as an Event::Cover, anything already being loaded is left to finish
+    pub fn want(&self, files: Vec<String>) {
+        let (lock, ready) = &*self.jobs;
+        let mut jobs = lock.lock().unwrap();
+        jobs.waiting = files.into_iter().filter(|x| !jobs.loading.contains(x)).collect();
+        ready.notify_all()
     }
+}
 
-    let cache_name = base64::encode_config(
-        image_path.to_str().unwrap().bytes().collect::<Vec<u8>>(),
-        base64::URL_SAFE
-    );
-    let cache_file = [cache_dir.to_str().unwrap(), &*cache_name].join("/");
+// shown until a cover has loaded, and for songs without one
+pub fn placeholder() -> Image { Image::from_rgba_unmultiplied([250, 250], NO_ART) }
+
+fn get_cover(
+    settings: &Settings, music_dir: &str, file: &str, covers: Option<&[String]>, limit: u64
+) -> Image {
+    gen_image(settings, music_dir, file, covers, limit).unwrap_or_else(|_| placeholder())
+}
+
+// in order: a cover file next to the song and then the picture embedded in it, both only if the music
+// directory is somewhere we can read it, then whatever mpd has
+fn gen_image(
+    settings: &Settings, music_dir: &str, file: &str, covers: Option<&[String]>, limit: u64
+) -> Result<Image> {
+    let image_path = search(music_dir, file, covers);
+    let song_path = Path::new(music_dir).join(file);
+    let key = match &image_path {
+        Ok(x) => cache::file_key(x)?,
+        Err(_) if !music_dir.is_empty() && song_path.is_file() => cache::file_key(&song_path)?,
+        // mpd can say when a remote song last changed without sending its cover
+        Err(_) => ["mpd:", file, ":", &last_modified(settings, file)?].join("")
+    };
+    if cache::is_miss(&key) {
+        bail!("No cover art for {}", file)
+    }
+    if let Some(x) = cache::get(&key) {
+        return Ok(x)
+    }
 
-    match Path::is_file(Path::new(&cache_file)) {
-        true => Ok(Image::from_rgba_unmultiplied([250, 250], &fs::read(&cache_file)?)),
-        false => {
-            let image = image::open(image_path)?;
-            let resized = image.resize_to_fill(250, 250, image::imageops::FilterType::Lanczos3);
-            let cache_buffer = resized.to_rgba8().into_vec();
-            fs::write(cache_file, &cache_buffer)?;
-            Ok(Image::from_rgba_unmultiplied([250, 250], &cache_buffer))
+    let image = match image_path {
+        // by what's in the file, a png called cover.jpg is common enough
+        Ok(x) => Reader::open(x)?.with_guessed_format()?.decode()?,
+        Err(_) => {
+            let picture = match embedded(music_dir, file) {
+                Some(x) => Some(x),
+                None => from_mpd(settings, file)?
+            };
+            match picture {
+                Some(x) => image::load_from_memory(&x)?,
+                None => {
+                    // remembered so the next look doesn't go back to mpd, if it can't be that's all it costs
+                    let _ = cache::put_miss(&key);
+                    bail!("No cover art for {}", file)
+                }
+            }
         }
+    };
+    let thumbnail = image
+        .resize_to_fill(250, 250, image::imageops::FilterType::Lanczos3)
+        .to_rgba8();
+    // the cover is in hand already, a cache that can't be written to only means resizing it again next time
+    let _ = cache::put(&key, &thumbnail, limit);
+    Ok(Image::from_rgba_unmultiplied([250, 250], &thumbnail))
+}
+
+fn embedded(music_dir: &str, file: &str) -> Option<Vec<u8>> {
+    match music_dir.is_empty() {
+        true => None,
+        false => embedded::picture(Path::new(&[music_dir, file].join("/")))
+            .ok()
+            .flatten()
+    }
+}
+
+// works over tcp too, so remote servers get covers. None when mpd has nothing for the song
+fn from_mpd(settings: &Settings, file: &str) -> Result<Option<Vec<u8>>> {
+    let mut raw = Raw::connect(settings)?;
+    // mpd only sends 8KiB at a time unless told otherwise, older versions don't know binarylimit
+    let _ = raw.command("binarylimit", &["1048576"]);
+    // albumart is an error rather than nothing when there's no cover file
+    if let Ok(Some(x)) = raw.binary("albumart", file) {
+        return Ok(Some(x))
     }
+    raw.binary("readpicture", file)
+}
+
+fn last_modified(settings: &Settings, file: &str) -> Result<String> {
+    Ok(Raw::connect(settings)?
+        .command("lsinfo", &[file])?
+        .into_iter()
+        .find(|(key, _)| key == "Last-Modified")
+        .map(|(_, value)| value)
+        .unwrap_or_default())
 }
 
-fn search(paths: &(&str, &str)) -> Result<PathBuf> {
-    let (music_dir, filepath) = paths;
-    let mut file_dir = filepath.split('/').collect::<Vec<&str>>();
-    file_dir.pop();
-    let cover_path = file_dir.join("/");
-    let dir_contents = read_dir([music_dir.to_string(), cover_path].join("/"))?;
-    for x in dir_contents.flatten() {
-        match x.file_name().to_ascii_lowercase().to_str().unwrap() {
-            "cover.jpeg" | "cover.jpg" | "cover.png" => return Ok(x.path()),
-            _ => {}
+// the first pattern with a match wins. the default ../ patterns are only tried from disc folders (CD1,
+// Disc 2, ...) so a single disc album doesn't pick up whatever is in the artist's folder, the user's own
+// patterns are tried as written
+fn search(music_dir: &str, file: &str, covers: Option<&[String]>) -> Result<PathBuf> {
+    if music_dir.is_empty() {
+        bail!("")
+    }
+    let dir = Path::new(music_dir).join(file);
+    let dir = dir.parent().unwrap();
+    let disc = DISC.get_or_init(|| Regex::new(r"(?i)^(cd|dis[ck])[ _-]*\d+$").unwrap());
+    let in_disc = dir
+        .file_name()
+        .map(|x| disc.is_match(&x.to_string_lossy()))
+        .unwrap_or(false);
+
+    let patterns = match covers {
+        Some(x) => x.iter().map(|y| y.as_str()).collect::<Vec<_>>(),
+        None => COVERS
+            .iter()
+            .filter(|x| in_disc || !x.starts_with("../"))
+            .copied()
+            .collect()
+    };
+    for pattern in patterns {
+        if let Some(x) = matches(dir, pattern).into_iter().next() {
+            return Ok(x)
         }
     }
     bail!("")
 }
+
+// image files matching a pattern where each part between the slashes can have * and ?, ignoring case.
+// sorted so which of several matches wins doesn't depend on the filesystem
+fn matches(dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut found = vec![dir.to_path_buf()];
+    for part in pattern.split('/') {
+        found = match part {
+            ".." => found
+                .into_iter()
+                .filter_map(|x| x.parent().map(|y| y.to_path_buf()))
+                .collect(),
+            _ => {
+                let glob = glob(part);
+                found
+                    .iter()
+                    .filter_map(|x| read_dir(x).ok())
+                    .flat_map(|x| x.flatten())
+                    .filter(|x| glob.is_match(&x.file_name().to_string_lossy()))
+                    .map(|x| x.path())
+                    .collect()
+            }
+        }
+    }
+    found.retain(|x| {
+        let extension = x.extension().map(|y| y.to_string_lossy().to_lowercase());
+        x.is_file()
+            && extension
+                .map(|y| EXTENSIONS.contains(&y.as_str()))
+                .unwrap_or(false)
+    });
+    found.sort();
+    found
+}
+
+fn glob(part: &str) -> Regex {
+    let body = part
+        .chars()
+        .map(|x| match x {
+            '*' => ".*".to_string(),
+            '?' => ".".to_string(),
+            _ => regex::escape(&x.to_string())
+        })
+        .collect::<Vec<_>>();
+    Regex::new(&["(?i)^", &body.join(""), "$"].join("")).unwrap()
+}
@@ -0,0 +1,470 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    iter::from_fn,
+    path::Path
+};
+
+use anyhow::{anyhow, bail, Result};
+
+// picture types are from the id3v2 spec, flac and vorbis comments use the same ones
+const FRONT_COVER: u32 = 3;
+
+// (picture type, image data)
+type Picture = (u32, Vec<u8>);
+
+// the picture embedded in a song, the front cover if there's more than one. None when there isn't one or
+// it's not a format we know
+pub fn picture(path: &Path) -> Result<Option<Vec<u8>>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    file.read_exact(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let pictures = match &magic {
+        [b'f', b'L', b'a', b'C', ..] => flac(&mut file)?,
+        [b'I', b'D', b'3', ..] => id3(&mut file)?,
+        [b'O', b'g', b'g', b'S', ..] => ogg(&mut file)?,
+        [_, _, _, _, b'f', b't', b'y', b'p'] => mp4(&mut file)?,
+        _ => vec![]
+    };
+    let front = pictures.iter().position(|x| x.0 == FRONT_COVER).unwrap_or(0);
+    Ok(pictures.into_iter().nth(front).map(|x| x.1))
+}
+
+// metadata blocks come before the audio, each with a one byte type (the top bit marks the last one) and
+// a three byte length
+fn flac(file: &mut (impl Read + Seek)) -> Result<Vec<Picture>> {
+    read_vec(file, 4)?;
+    let mut pictures = vec![];
+    loop {
+        let header = read_vec(file, 4)?;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+        match header[0] & 0x7f {
+            4 => pictures.extend(vorbis_comments(&read_vec(file, len)?)?),
+            6 => pictures.push(flac_picture(&read_vec(file, len)?)?),
+            _ => {
+                file.seek(SeekFrom::Current(len as i64))?;
+            }
+        }
+        if header[0] & 0x80 != 0 {
+            return Ok(pictures)
+        }
+    }
+}
+
+// a flac PICTURE block, also what METADATA_BLOCK_PICTURE holds once it's base64 decoded
+fn flac_picture(block: &[u8]) -> Result<Picture> {
+    let mut x = Bytes(block);
+    let kind = x.u32_be()?;
+    let mime = x.u32_be()? as usize;
+    x.take(mime)?;
+    let description = x.u32_be()? as usize;
+    x.take(description)?;
+    // width, height, colour depth and palette size
+    x.take(16)?;
+    let len = x.u32_be()? as usize;
+    Ok((kind, x.take(len)?.to_vec()))
+}
+
+fn vorbis_comments(block: &[u8]) -> Result<Vec<Picture>> {
+    let key = b"METADATA_BLOCK_PICTURE=";
+    let mut x = Bytes(block);
+    let vendor = x.u32_le()? as usize;
+    x.take(vendor)?;
+
+    let mut pictures = vec![];
+    for _ in 0..x.u32_le()? {
+        let len = x.u32_le()? as usize;
+        let comment = x.take(len)?;
+        if comment.len() > key.len() && comment[..key.len()].eq_ignore_ascii_case(key) {
+            if let Ok(y) = base64::decode(&comment[key.len()..]) {
+                pictures.push(flac_picture(&y)?)
+            }
+        }
+    }
+    Ok(pictures)
+}
+
+// vorbis and opus keep their comments in the second packet, which a big picture spreads over many pages
+fn ogg(file: &mut impl Read) -> Result<Vec<Picture>> {
+    let mut packets = 0;
+    let mut packet = vec![];
+    loop {
+        let header = read_vec(file, 27)?;
+        if &header[..4] != b"OggS" {
+            bail!("Bad ogg page")
+        }
+        // a segment shorter than 255 bytes ends a packet
+        for len in read_vec(file, header[26] as usize)? {
+            packet.extend(read_vec(file, len as usize)?);
+            if len == 255 {
+                continue
+            }
+            packets += 1;
+            if packets == 2 {
+                return match () {
+                    _ if packet.starts_with(b"\x03vorbis") => vorbis_comments(&packet[7..]),
+                    _ if packet.starts_with(b"OpusTags") => vorbis_comments(&packet[8..]),
+                    _ => Ok(vec![])
+                }
+            }
+            packet.clear()
+        }
+    }
+}
+
+fn id3(file: &mut impl Read) -> Result<Vec<Picture>> {
+    let header = read_vec(file, 10)?;
+    let (version, flags) = (header[3], header[5]);
+    let mut tag = read_vec(file, syncsafe(&header[6..10]))?;
+    // before 2.4 unsynchronisation covers the whole tag, after it's per frame
+    if flags & 0x80 != 0 && version < 4 {
+        tag = resync(&tag)
+    }
+
+    let mut x = Bytes(&tag);
+    if flags & 0x40 != 0 {
+        match version {
+            3 => {
+                let len = x.u32_be()? as usize;
+                x.take(len)?;
+            }
+            4 => {
+                let len = syncsafe(x.take(4)?);
+                x.take(len.saturating_sub(4))?;
+            }
+            _ => {}
+        }
+    }
+
+    let mut pictures = vec![];
+    let header_len = match version {
+        2 => 6,
+        _ => 10
+    };
+    while x.0.len() >= header_len {
+        let (id, len, flags) = match version {
+            2 => {
+                let h = x.take(6)?;
+                (&h[..3], u32::from_be_bytes([0, h[3], h[4], h[5]]) as usize, 0)
+            }
+            3 => {
+                let h = x.take(10)?;
+                (&h[..4], Bytes(&h[4..8]).u32_be()? as usize, h[9])
+            }
+            _ => {
+                let h = x.take(10)?;
+                (&h[..4], syncsafe(&h[4..8]), h[9])
+            }
+        };
+        // the rest is padding
+        if id[0] == 0 {
+            break
+        }
+        let mut data = x.take(len)?.to_vec();
+        if id != b"APIC" && id != b"PIC" {
+            continue
+        }
+
+        // compressed and encrypted frames are left alone, grouping and data length just get skipped
+        let (compressed, grouped) = match version {
+            3 => (flags & 0xc0 != 0, flags & 0x20 != 0),
+            4 => (flags & 0x0c != 0, flags & 0x40 != 0),
+            _ => (false, false)
+        };
+        if compressed {
+            continue
+        }
+        if version == 4 && flags & 0x02 != 0 {
+            data = resync(&data)
+        }
+        let skip = grouped as usize + (version == 4 && flags & 0x01 != 0) as usize * 4;
+        if let Some(y) = data.get(skip..).and_then(|y| apic(y, version == 2).ok()) {
+            pictures.push(y)
+        }
+    }
+    Ok(pictures)
+}
+
+// APIC, or PIC in 2.2 which has a three letter format rather than a mime type
+fn apic(data: &[u8], v22: bool) -> Result<Picture> {
+    let mut x = Bytes(data);
+    let encoding = x.take(1)?[0];
+    match v22 {
+        true => x.take(3)?,
+        false => x.until_nul(1)?
+    };
+    let kind = x.take(1)?[0] as u32;
+    // utf-16 descriptions end with two zero bytes
+    x.until_nul(match encoding {
+        1 | 2 => 2,
+        _ => 1
+    })?;
+    Ok((kind, x.0.to_vec()))
+}
+
+fn syncsafe(b: &[u8]) -> usize { b.iter().fold(0, |x, y| (x << 7) | (*y as usize & 0x7f)) }
+
+// unsynchronisation puts a zero byte after every 0xff
+fn resync(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    for (i, x) in data.iter().enumerate() {
+        if !(*x == 0 && i > 0 && data[i - 1] == 0xff) {
+            out.push(*x)
+        }
+    }
+    out
+}
+
+// moov can be anywhere at the top level, often after the audio, so everything else gets skipped over
+fn mp4(file: &mut (impl Read + Seek)) -> Result<Vec<Picture>> {
+    let end = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(0))?;
+    loop {
+        let header = match read_vec(file, 8) {
+            Ok(x) => x,
+            Err(_) => return Ok(vec![])
+        };
+        let len = match Bytes(&header).u32_be()? {
+            // a 64 bit length follows the type
+            1 => Bytes(&read_vec(file, 8)?).u64_be()?.saturating_sub(16),
+            // runs to the end of the file, which only the audio does
+            0 => return Ok(vec![]),
+            x => (x as u64).saturating_sub(8)
+        };
+        // a corrupt length could ask for far more than there is
+        if len > end.saturating_sub(file.stream_position()?) {
+            bail!("Bad mp4 atom length")
+        }
+        match &header[4..8] == b"moov" {
+            true => return Ok(covr(&read_vec(file, len as usize)?)),
+            false => {
+                file.seek(SeekFrom::Current(len as i64))?;
+            }
+        }
+    }
+}
+
+// moov > udta > meta > ilst > covr, with each picture in a data atom of its own after 8 bytes of type and
+// locale. there are no picture types so the first one counts as the front
+fn covr(moov: &[u8]) -> Vec<Picture> {
+    let meta = child(moov, b"udta")
+        .and_then(|x| child(x, b"meta"))
+        .or_else(|| child(moov, b"meta"));
+    // meta's children come after its version and flags
+    meta.and_then(|x| x.get(4..))
+        .and_then(|x| child(x, b"ilst"))
+        .and_then(|x| child(x, b"covr"))
+        .map(|x| {
+            atoms(x)
+                .filter(|y| y.0 == b"data")
+                .filter_map(|y| y.1.get(8..))
+                .map(|y| (FRONT_COVER, y.to_vec()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn child<'a>(data: &'a [u8], name: &[u8]) -> Option<&'a [u8]> {
+    atoms(data).find(|x| x.0 == name).map(|x| x.1)
+}
+
+// (type, contents) for each atom in data
+fn atoms(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    from_fn(move || {
+        let len = Bytes(data).u32_be().ok()? as usize;
+        if len < 8 || len > data.len() {
+            return None
+        }
+        let (atom, rest) = data.split_at(len);
+        data = rest;
+        Some((&atom[4..8], &atom[8..]))
+    })
+}
+
+fn read_vec(file: &mut impl Read, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    file.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+// reads through a buffer, erroring rather than panicking on anything cut short
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.0.len() {
+            bail!("Embedded picture cut short")
+        }
+        let (x, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(x)
+    }
+
+    fn u32_be(&mut self) -> Result<u32> { Ok(u32::from_be_bytes(self.take(4)?.try_into()?)) }
+
+    fn u32_le(&mut self) -> Result<u32> { Ok(u32::from_le_bytes(self.take(4)?.try_into()?)) }
+
+    fn u64_be(&mut self) -> Result<u64> { Ok(u64::from_be_bytes(self.take(8)?.try_into()?)) }
+
+    // up to a zero terminator of the given width, which is skipped
+    fn until_nul(&mut self, width: usize) -> Result<&'a [u8]> {
+        let end = self
+            .0
+            .chunks(width)
+            .position(|x| x.iter().all(|y| *y == 0))
+            .ok_or_else(|| anyhow!("Unterminated string in embedded picture"))?;
+        let s = self.take(end * width)?;
+        self.take(width)?;
+        Ok(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Cursor};
+
+    use super::*;
+
+    fn be32(x: usize) -> Vec<u8> { (x as u32).to_be_bytes().to_vec() }
+
+    fn le32(x: usize) -> Vec<u8> { (x as u32).to_le_bytes().to_vec() }
+
+    fn to_syncsafe(x: usize) -> Vec<u8> { [21, 14, 7, 0].iter().map(|y| (x >> y) as u8 & 0x7f).collect() }
+
+    fn flac_picture_block(kind: usize, data: &[u8]) -> Vec<u8> {
+        [
+            &be32(kind),
+            &be32(9),
+            &b"image/png"[..],
+            &be32(0),
+            &[0; 16],
+            &be32(data.len()),
+            data
+        ]
+        .concat()
+    }
+
+    fn flac_file() -> Vec<u8> {
+        let back = flac_picture_block(4, b"back");
+        let front = flac_picture_block(3, b"front");
+        [
+            &b"fLaC"[..],
+            // streaminfo, then the pictures with the last block flagged
+            &[0, 0, 0, 34],
+            &[0; 34],
+            &[6, 0, 0, back.len() as u8],
+            &back,
+            &[0x86, 0, 0, front.len() as u8],
+            &front
+        ]
+        .concat()
+    }
+
+    fn vorbis_comment_block(picture: &[u8]) -> Vec<u8> {
+        let comment = [
+            &b"METADATA_BLOCK_PICTURE="[..],
+            base64::encode(picture).as_bytes()
+        ]
+        .concat();
+        [&le32(6), &b"vendor"[..], &le32(1), &le32(comment.len()), &comment].concat()
+    }
+
+    fn atom(name: &[u8], body: &[u8]) -> Vec<u8> { [&be32(body.len() + 8), name, body].concat() }
+
+    #[test]
+    fn flac_pictures() {
+        let pictures = flac(&mut Cursor::new(flac_file())).unwrap();
+        assert_eq!(pictures, vec![(4, b"back".to_vec()), (3, b"front".to_vec())]);
+    }
+
+    #[test]
+    fn front_cover_comes_first() {
+        let name = ["rinse-front-cover-", &std::process::id().to_string(), ".flac"].join("");
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, flac_file()).unwrap();
+        let picture = picture(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(picture.unwrap(), Some(b"front".to_vec()));
+    }
+
+    #[test]
+    fn id3_apic() {
+        // utf-16 description with a bom, the picture starts after its two byte terminator
+        let apic = [
+            &[1][..],
+            b"image/jpeg\0",
+            &[3],
+            &[0xff, 0xfe, b'a', 0, 0, 0],
+            b"jpeg"
+        ]
+        .concat();
+        let frame = [&b"APIC"[..], &be32(apic.len()), &[0, 0], &apic].concat();
+        let tag = [&frame[..], &[0; 10]].concat();
+        let file = [&b"ID3"[..], &[3, 0, 0], &to_syncsafe(tag.len()), &tag].concat();
+        assert_eq!(id3(&mut Cursor::new(file)).unwrap(), vec![(3, b"jpeg".to_vec())]);
+    }
+
+    #[test]
+    fn id3_unsynchronised_frame() {
+        // 2.4 with the frame's unsynchronisation flag, the zero after 0xff isn't part of the picture
+        let apic = [&[0][..], b"image/jpeg\0", &[3], b"\0", &[0xff, 0, 0xd8]].concat();
+        let frame = [&b"APIC"[..], &to_syncsafe(apic.len()), &[0, 0x02], &apic].concat();
+        let file = [&b"ID3"[..], &[4, 0, 0], &to_syncsafe(frame.len()), &frame].concat();
+        assert_eq!(id3(&mut Cursor::new(file)).unwrap(), vec![(3, vec![0xff, 0xd8])]);
+    }
+
+    #[test]
+    fn ogg_opus_tags() {
+        let packets = [
+            b"OpusHead".to_vec(),
+            [
+                &b"OpusTags"[..],
+                &vorbis_comment_block(&flac_picture_block(3, b"cover"))
+            ]
+            .concat()
+        ];
+        let mut segments = vec![];
+        for x in &packets {
+            segments.extend(vec![255; x.len() / 255]);
+            segments.push((x.len() % 255) as u8)
+        }
+        let file = [
+            &b"OggS"[..],
+            &[0; 22],
+            &[segments.len() as u8],
+            &segments,
+            &packets.concat()
+        ]
+        .concat();
+        assert_eq!(ogg(&mut Cursor::new(file)).unwrap(), vec![(3, b"cover".to_vec())]);
+    }
+
+    #[test]
+    fn mp4_covr() {
+        let data = atom(b"data", &[&[0, 0, 0, 13][..], &[0; 4], b"cover"].concat());
+        let meta = atom(
+            b"meta",
+            &[&[0; 4][..], &atom(b"ilst", &atom(b"covr", &data))].concat()
+        );
+        let file = [
+            atom(b"ftyp", b"M4A \0\0\0\0"),
+            atom(b"mdat", &[0; 16]),
+            atom(b"moov", &atom(b"udta", &meta))
+        ];
+        assert_eq!(mp4(&mut Cursor::new(file.concat())).unwrap(), vec![(
+            3,
+            b"cover".to_vec()
+        )]);
+    }
+
+    #[test]
+    fn mp4_bad_length() {
+        let file = [atom(b"ftyp", b"M4A \0\0\0\0"), be32(0x7fffffff), b"moov".to_vec()].concat();
+        assert_eq!(
+            mp4(&mut Cursor::new(file)).unwrap_err().to_string(),
+            "Bad mp4 atom length"
+        );
+    }
+}
@@ -1,102 +1,705 @@
-use std::{collections::BTreeMap, env::var, fs, net::TcpStream, path::Path, time::Instant};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env::var,
+    fs,
+    path::Path,
+    sync::mpsc::{Receiver, Sender},
+    time::Instant
+};
 
+use anyhow::{bail, Result};
 use eframe::{
-    egui::{Color32, TextureId, Vec2},
-    epi::Image
+    egui::{Color32, TextureId},
+    epi::{Frame, Image}
 };
-use mpd::{Client, Song, State, Status};
+use mpd::{Song, State, Status};
 
-use super::{images, search, utils};
+use super::{
+    config::Config,
+    conn::Connection,
+    history,
+    images::{self, Loader},
+    keys::Keymap,
+    library::{self, Add},
+    proto::{Command, Raw},
+    queue::{self, Edit},
+    search::{Matching, Searcher},
+    transport::{self, Control},
+    utils,
+    worker::Event
+};
 
 pub struct Rinse {
     pub data: Data
 }
 
 pub struct Data {
-    pub colours:          Colours,
-    pub update_timer:     Option<Instant>,
-    pub mpc:              Client<TcpStream>,
-    pub paths:            (String, String),
-    pub queue:            Vec<Song>,
-    pub current_pos:      usize,
-    pub state:            State,
-    pub showing_info:     usize,
-    pub cover:            Option<(Vec2, TextureId)>,
-    pub info_title:       Option<String>,
-    pub info_artist:      Option<String>,
-    pub info_album:       Option<String>,
-    pub info_duration:    Option<String>,
-    pub info_date:        Option<String>,
-    pub elapsed:          Option<i64>,
-    pub duration:         Option<i64>,
-    pub switcher:         String,
-    pub switcher_timer:   Option<Instant>,
-    pub switcher_cycle:   u8,
-    pub search_query:     String,
-    pub list:             Vec<SearchResult>,
-    pub selected:         usize,
-    pub selected_pos:     usize,
-    pub interacted:       bool,
-    pub need_list_scroll: bool
+    pub colours:           Colours,
+    pub config:            Config,
+    pub events:            Option<(Sender<Event>, Receiver<Event>)>,
+    pub mpc:               Connection,
+    pub status:            Status,
+    // the music directory and the song whose info is showing
+    pub paths:             (String, String),
+    pub queue:             Vec<Song>,
+    pub library:           Option<Vec<Song>>,
+    pub mode:              Mode,
+    pub library_loading:   bool,
+    pub current_pos:       usize,
+    pub state:             State,
+    pub showing_info:      usize,
+    // cover textures by song file, only for the rows around the selection, see request_covers
+    pub covers:            HashMap<String, TextureId>,
+    pub placeholder:       Option<TextureId>,
+    pub loader:            Option<Loader>,
+    pub info_title:        Option<String>,
+    pub info_artist:       Option<String>,
+    pub info_album:        Option<String>,
+    pub info_duration:     Option<String>,
+    pub info_date:         Option<String>,
+    pub elapsed:           Option<i64>,
+    pub elapsed_at:        Instant,
+    pub duration:          Option<i64>,
+    pub switcher:          String,
+    pub switcher_timer:    Option<Instant>,
+    pub switcher_cycle:    u8,
+    // when the switcher asked to be woken up to rotate, see worker::repaint_at
+    pub switcher_wake:     Option<Instant>,
+    // the pointer is over the options line
+    pub hold_switcher:     bool,
+    pub searcher:          Option<Searcher>,
+    pub search_query:      String,
+    pub search_error:      Option<String>,
+    pub exact_case:        bool,
+    // how plain words are matched, alt+m cycles it
+    pub matching:          Matching,
+    // which query the list is waiting on, see Searcher::query
+    pub search_generation: usize,
+    pub searching:         bool,
+    pub list:              Vec<SearchResult>,
+    // a queue id to keep selected when the pending results come in
+    pub list_anchor:       Option<u32>,
+    pub selected:          usize,
+    pub selected_pos:      usize,
+    pub error:             Option<String>,
+    pub notice:            Option<String>,
+    pub interacted:        bool,
+    pub need_list_scroll:  bool,
+    // songs picked for the next action along with the selection, see mark_key
+    pub marked:            BTreeSet<usize>,
+    // past searches oldest first, and which one up/down is showing
+    pub search_history:    Vec<String>,
+    pub history_pos:       Option<usize>,
+    // what was typed before up was first pressed, down past the newest search brings it back
+    pub history_draft:     String,
+    // the query was replaced, so the cursor has to go to the end of it
+    pub query_recalled:    bool,
+    // the ctrl+r overlay
+    pub reverse_search:    Option<ReverseSearch>,
+    pub keymap:            Keymap,
+    // how many rows fit in the list, for page up/down
+    pub page_rows:         usize
+}
+
+pub struct ReverseSearch {
+    pub text:  String,
+    // index into search_history of the search being previewed
+    pub found: Option<usize>,
+    before:    String
+}
+
+// older searches are dropped past this
+const MAX_SEARCHES: usize = 500;
+
+impl Data {
+    // whatever the list is currently built from, SearchResult.pos indexes into this
+    pub fn songs(&self) -> &[Song] {
+        match self.mode {
+            Mode::Queue => &self.queue,
+            Mode::Library => self.library.as_deref().unwrap_or_default()
+        }
+    }
+
+    // the list gets replaced once the results come back, see set_list
+    pub fn rebuild_list(&mut self) {
+        if let Some(x) = &self.searcher {
+            self.search_generation = x.query(&self.search_query, self.mode, self.exact_case, self.matching);
+            self.searching = true;
+            self.list_anchor = None
+        }
+    }
+
+    pub fn set_list(&mut self, generation: usize, results: Result<Vec<SearchResult>>) {
+        if generation != self.search_generation {
+            return
+        }
+        self.searching = false;
+        match results {
+            Ok(x) => {
+                self.list = x;
+                self.search_error = None
+            }
+            Err(e) => {
+                self.list = vec![];
+                self.search_error = Some(e.to_string())
+            }
+        }
+
+        match self.list_anchor.take() {
+            // the queue changed under us, keep the same song selected wherever it ended up, if it's gone
+            // fall back to the top
+            Some(id) => self.selected = self.list.iter().position(|x| x.id == id).unwrap_or(0),
+            None => match self.search_query.is_empty() && self.mode == Mode::Queue {
+                true => {
+                    self.selected = self.current_pos;
+                    self.interacted = false
+                }
+                false => {
+                    self.selected = 0;
+                    self.interacted = true
+                }
+            }
+        }
+        self.selected_pos = self.list.get(self.selected).map(|x| x.pos).unwrap_or(0);
+        self.need_list_scroll = true
+    }
+
+    // enter or a double click on a row, returns whether we're done and the window can close
+    pub fn activate(&mut self, index: usize, how: Add) -> Result<bool> {
+        if index >= self.list.len() {
+            return Ok(false)
+        }
+        self.finish_reverse_search(true);
+        self.remember_search();
+
+        let targets = self.targets(index);
+        if targets.is_empty() {
+            return Ok(false)
+        }
+        match self.mode {
+            // in the queue the songs are already there, they just move
+            Mode::Queue if matches!(how, Add::Next) => self.edit_queue(index, Edit::Next).map(|_| false),
+            Mode::Queue if matches!(how, Add::Append) => self.edit_queue(index, Edit::Bottom).map(|_| false),
+            Mode::Queue => {
+                // several go after the current song so they play one after the other
+                if targets.len() > 1 {
+                    self.edit_queue(index, Edit::Next)?
+                }
+                // the first of them in queue order
+                let id = self
+                    .queue
+                    .iter()
+                    .filter_map(|x| x.place.map(|y| y.id.0 as usize))
+                    .find(|x| targets.contains(x))
+                    .unwrap_or(targets[0]);
+                let file = self.queued(id).map(|x| x.file.to_owned());
+                utils::play(&mut self.mpc, id as u32)?;
+                if let Some(x) = file {
+                    // the song is playing already, a history file that can't be written only costs the ranking
+                    let _ = history::record(&x);
+                }
+                self.marked.clear();
+                Ok(true)
+            }
+            Mode::Library => {
+                let songs = targets.iter().map(|x| &self.songs()[*x]).collect::<Vec<_>>();
+                let current = self.status.song.map(|x| x.pos as usize);
+                let commands = library::add(&songs, how, current);
+                let what = match songs.len() {
+                    1 => utils::gen_title(songs[0]),
+                    x => [&x.to_string(), " songs"].join("")
+                };
+                let first = songs[0].file.to_owned();
+                let quit = matches!(how, Add::Play);
+                self.notice = match how {
+                    Add::Play => None,
+                    Add::Append => Some(["added ", &what].join("")),
+                    Add::Next => Some(["playing next ", &what].join(""))
+                };
+                let added = self.send(&commands)?;
+                if quit {
+                    // not by position, the queue we know about may already be out of date
+                    let id = added
+                        .into_iter()
+                        .find(|(key, _)| key == "Id")
+                        .and_then(|(_, value)| value.parse::<u32>().ok());
+                    match id {
+                        Some(x) => utils::play(&mut self.mpc, x)?,
+                        None => bail!("mpd didn't say where the songs were added!")
+                    }
+                    let _ = history::record(&first);
+                }
+                self.marked.clear();
+                Ok(quit)
+            }
+        }
+    }
+
+    // the list catches up when mpd says the queue changed, see Event::Queue
+    pub fn edit_queue(&mut self, index: usize, edit: Edit) -> Result<()> {
+        if self.mode != Mode::Queue {
+            bail!("Only songs in the queue can be moved or removed!")
+        }
+        let targets = self.targets(index);
+        if targets.is_empty() {
+            return Ok(())
+        }
+        let ids = targets.iter().map(|x| *x as u32).collect::<Vec<_>>();
+        let queue = self
+            .queue
+            .iter()
+            .map(|x| x.place.map(|y| y.id.0).unwrap_or_default())
+            .collect::<Vec<_>>();
+        let current = self.status.song.map(|x| x.id.0);
+        self.send(&queue::edit(edit, &ids, &queue, current))?;
+
+        if edit == Edit::Remove && self.marked.is_empty() && index == self.selected {
+            // a neighbour stays selected rather than jumping back to the top
+            let neighbour = match index + 1 < self.list.len() {
+                true => index + 1,
+                false => index.saturating_sub(1)
+            };
+            self.selected = neighbour;
+            self.selected_pos = self.list[neighbour].pos
+        }
+        let what = match (targets.len(), self.queued(targets[0])) {
+            (1, Some(x)) => utils::gen_title(x),
+            (1, None) => "1 song".to_string(),
+            (x, _) => [&x.to_string(), " songs"].join("")
+        };
+        self.notice = Some(match edit {
+            Edit::Next => ["playing next ", &what].join(""),
+            Edit::Top => ["moved to the top ", &what].join(""),
+            Edit::Bottom => ["moved to the bottom ", &what].join(""),
+            Edit::Remove => ["removed ", &what].join(""),
+            Edit::Crop => ["removed everything but ", &what].join("")
+        });
+        self.marked.clear();
+        self.interacted = true;
+        Ok(())
+    }
+
+    // queue ids in the queue and positions in the library, neither changes when the list is searched again
+    fn mark_key(&self, result: &SearchResult) -> usize {
+        match self.mode {
+            Mode::Queue => result.id as usize,
+            Mode::Library => result.pos
+        }
+    }
+
+    pub fn is_marked(&self, result: &SearchResult) -> bool { self.marked.contains(&self.mark_key(result)) }
+
+    pub fn toggle_mark(&mut self, index: usize) {
+        if let Some(x) = self.list.get(index) {
+            let key = self.mark_key(x);
+            if !self.marked.remove(&key) {
+                self.marked.insert(key);
+            }
+        }
+    }
+
+    // shift+click, everything between the two rows
+    pub fn mark_range(&mut self, from: usize, to: usize) {
+        let keys = self
+            .list
+            .iter()
+            .take(from.max(to) + 1)
+            .skip(from.min(to))
+            .map(|x| self.mark_key(x))
+            .collect::<Vec<_>>();
+        self.marked.extend(keys)
+    }
+
+    // every result, or none of them when they're all marked already
+    pub fn mark_all(&mut self) {
+        let keys = self
+            .list
+            .iter()
+            .map(|x| self.mark_key(x))
+            .collect::<BTreeSet<_>>();
+        match keys.is_subset(&self.marked) {
+            true => self.marked.retain(|x| !keys.contains(x)),
+            false => self.marked.extend(keys)
+        }
+    }
+
+    // what an action applies to, the marked songs or just the one at index when nothing is marked. queue
+    // ids in the queue, the list can still have positions from before the queue last changed, and positions
+    // in songs() in the library
+    fn targets(&self, index: usize) -> Vec<usize> {
+        if self.marked.is_empty() {
+            return self
+                .list
+                .get(index)
+                .map(|x| vec![self.mark_key(x)])
+                .unwrap_or_default()
+        }
+        match self.mode {
+            Mode::Queue => self.marked.iter().copied().collect(),
+            Mode::Library => self
+                .marked
+                .iter()
+                .copied()
+                .filter(|x| *x < self.songs().len())
+                .collect()
+        }
+    }
+
+    // the queue song with this id, if it's still there
+    fn queued(&self, id: usize) -> Option<&Song> {
+        self.queue
+            .iter()
+            .find(|x| x.place.map(|y| y.id.0 as usize) == Some(id))
+    }
+
+    fn send(&self, commands: &[Command]) -> Result<Vec<(String, String)>> {
+        Raw::connect(&self.mpc.settings)?.command_list(commands)
+    }
+
+    // where the current song is in milliseconds, counting on from the last status while playing
+    pub fn position(&self) -> i64 {
+        match self.state {
+            State::Play => self.elapsed.unwrap_or(0) + self.elapsed_at.elapsed().as_millis() as i64,
+            _ => self.elapsed.unwrap_or(0)
+        }
+    }
+
+    pub fn set_status(&mut self, mut status: Status) {
+        // only the idle connection asks for this, see worker::replay_gain_mode. the first time it's known
+        // isn't a change
+        match status.replaygain {
+            Some(_) if self.status.replaygain.is_none() => self.status.replaygain = status.replaygain,
+            Some(_) => {}
+            None => status.replaygain = self.status.replaygain
+        }
+        let options = |x: &Status| {
+            (
+                x.volume,
+                x.repeat,
+                x.random,
+                x.single,
+                x.consume,
+                x.crossfade,
+                x.replaygain
+            )
+        };
+        let options_changed = options(&status) != options(&self.status);
+
+        let current_pos = status.song.map(|x| x.pos as usize).unwrap_or(self.current_pos);
+        let more = match status.nextsong.is_some() {
+            true => 2,
+            false => 3
+        };
+
+        if current_pos != self.current_pos {
+            let mut cycle = None;
+            if !self.interacted && self.mode == Mode::Queue {
+                self.selected = current_pos;
+                self.selected_pos = current_pos;
+                cycle = Some(more);
+            }
+
+            self.switcher_cycle = cycle.unwrap_or(match self.selected == current_pos {
+                true => more,
+                false if more == 2 => 1,
+                false => 0
+            });
+            self.switcher_timer = Some(Instant::now());
+            self.switcher = utils::gen_switcher(self.switcher_cycle, &status, &self.queue);
+
+            self.current_pos = current_pos
+        }
+        // a change to the options (from here or anywhere else) gets shown straight away
+        else if options_changed || self.switcher_cycle == 3 {
+            if self.switcher_cycle != 3 {
+                self.switcher_cycle = 3;
+                self.switcher_timer = Some(Instant::now())
+            }
+            self.switcher = utils::gen_switcher(3, &status, &self.queue)
+        }
+
+        self.state = status.state;
+        self.duration = status.duration.map(|x| x.to_owned().num_milliseconds());
+        self.elapsed = status.elapsed.map(|x| x.to_owned().num_milliseconds());
+        self.elapsed_at = Instant::now();
+        self.status = status
+    }
+
+    // the idle connection will report the change too, but asking straight away means the info panel
+    // doesn't lag behind the key press
+    pub fn control(&mut self, control: Control) {
+        let elapsed = self.position();
+        let result = transport::send(&mut self.mpc, control, &self.status, elapsed)
+            .and_then(|_| self.mpc.run(|x| x.status()));
+        match result {
+            Ok(x) => self.set_status(x),
+            Err(e) => self.error = Some(e.to_string())
+        }
+    }
+
+    // the showing song then the rows either side of the selection, nearest first
+    fn cover_window(&self) -> Vec<String> {
+        let mut files = match self.paths.1.is_empty() {
+            true => vec![],
+            false => vec![self.paths.1.to_owned()]
+        };
+        for i in 1..=images::PREFETCH {
+            for row in [self.selected.checked_sub(i), Some(self.selected + i)]
+                .into_iter()
+                .flatten()
+            {
+                if let Some(x) = self.list.get(row).and_then(|x| self.songs().get(x.pos)) {
+                    if !files.contains(&x.file) {
+                        files.push(x.file.to_owned())
+                    }
+                }
+            }
+        }
+        files
+    }
+
+    // frees the textures of covers that are out of the window and asks for the ones missing from it
+    pub fn request_covers(&mut self, frame: &Frame) {
+        let window = self.cover_window();
+        let stale = self
+            .covers
+            .keys()
+            .filter(|x| !window.contains(x))
+            .cloned()
+            .collect::<Vec<_>>();
+        for x in stale {
+            if let Some(y) = self.covers.remove(&x) {
+                frame.free_texture(y)
+            }
+        }
+        if let Some(x) = &self.loader {
+            x.want(
+                window
+                    .into_iter()
+                    .filter(|y| !self.covers.contains_key(y))
+                    .collect()
+            )
+        }
+    }
+
+    // the selection may have moved on while it loaded
+    pub fn add_cover(&mut self, file: String, image: Image, frame: &Frame) {
+        if self.covers.contains_key(&file) || !self.cover_window().contains(&file) {
+            return
+        }
+        self.covers.insert(file, frame.alloc_texture(image));
+    }
+
+    // past either end goes to that end
+    pub fn select(&mut self, index: usize) {
+        if self.list.is_empty() {
+            return
+        }
+        self.selected = index.min(self.list.len() - 1);
+        self.need_list_scroll = true;
+        self.interacted = true
+    }
+
+    // only searches that something was picked from are worth keeping
+    fn remember_search(&mut self) {
+        let query = self.search_query.trim().to_owned();
+        if query.is_empty() {
+            return
+        }
+        self.search_history.retain(|x| *x != query);
+        self.search_history.push(query);
+        let len = self.search_history.len();
+        if len > MAX_SEARCHES {
+            self.search_history.drain(..len - MAX_SEARCHES);
+        }
+        self.history_pos = None;
+        // the search already happened, one that can't be saved is only missing from up/down next time
+        let _ = history::save_searches(&self.search_history);
+    }
+
+    // up/down (ctrl+p/ctrl+n) step through past searches, newest first
+    pub fn recall_search(&mut self, older: bool) {
+        let len = self.search_history.len();
+        let pos = match (self.history_pos, older) {
+            (None, true) if len > 0 => {
+                self.history_draft = self.search_query.clone();
+                Some(len - 1)
+            }
+            (None, _) => return,
+            (Some(0), true) => return,
+            (Some(x), true) => Some(x - 1),
+            (Some(x), false) if x + 1 < len => Some(x + 1),
+            (Some(_), false) => None
+        };
+        self.search_query = match pos {
+            Some(x) => self.search_history[x].clone(),
+            None => std::mem::take(&mut self.history_draft)
+        };
+        self.history_pos = pos;
+        self.query_recalled = true;
+        self.rebuild_list()
+    }
+
+    // ctrl+r opens the overlay, pressed again it looks further back for the same text
+    pub fn reverse_search(&mut self) {
+        match &self.reverse_search {
+            Some(_) => self.find_reverse_search(true),
+            None => {
+                self.reverse_search = Some(ReverseSearch {
+                    text:   String::new(),
+                    found:  None,
+                    before: self.search_query.clone()
+                });
+                self.query_recalled = true
+            }
+        }
+    }
+
+    // past searches containing the overlay's text, newest first
+    pub fn reverse_matches(&self) -> Vec<usize> {
+        let text = match &self.reverse_search {
+            Some(x) => x.text.to_lowercase(),
+            None => return vec![]
+        };
+        (0..self.search_history.len())
+            .rev()
+            .filter(|x| self.search_history[*x].to_lowercase().contains(&text))
+            .collect()
+    }
+
+    // the newest match for the text, or with older the next one past the current match. whatever is found
+    // gets searched for straight away so the list previews it
+    pub fn find_reverse_search(&mut self, older: bool) {
+        let matches = self.reverse_matches();
+        let x = match &mut self.reverse_search {
+            Some(x) => x,
+            None => return
+        };
+        let found = match (older, x.found) {
+            (true, Some(f)) => matches.into_iter().find(|y| *y < f).or(Some(f)),
+            (true, None) => matches.first().copied(),
+            (false, _) if x.text.is_empty() => None,
+            (false, _) => matches.first().copied()
+        };
+        if found == x.found {
+            return
+        }
+        x.found = found;
+        self.search_query = match found {
+            Some(i) => self.search_history[i].clone(),
+            None => x.before.clone()
+        };
+        self.rebuild_list()
+    }
+
+    // enter keeps the search that was found, escape goes back to what was there before
+    pub fn finish_reverse_search(&mut self, accept: bool) {
+        if let Some(x) = self.reverse_search.take() {
+            if !accept && self.search_query != x.before {
+                self.search_query = x.before;
+                self.rebuild_list()
+            }
+            self.history_pos = None;
+            self.query_recalled = true
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Queue,
+    Library
 }
 
 pub trait Setup {
-    fn setup(stuff: (Client<TcpStream>, Status, String)) -> Self;
+    fn setup(stuff: (Connection, Status, String, Config)) -> Self;
 }
 
 impl Setup for Rinse {
-    fn setup(stuff: (Client<TcpStream>, Status, String)) -> Self {
-        let (mut mpc, status, music_dir) = stuff;
+    fn setup(stuff: (Connection, Status, String, Config)) -> Self {
+        let (mut mpc, status, music_dir, config) = stuff;
 
-        let current_pos = status.song.unwrap().pos as usize;
+        // nothing might be playing, or even queued
+        let current_pos = status.song.map(|x| x.pos as usize).unwrap_or(0);
         let elapsed = status.elapsed.map(|x| x.to_owned().num_milliseconds());
         let duration = status.duration.map(|x| x.to_owned().num_milliseconds());
 
-        let queue = mpc.queue().unwrap();
-        let song = SongInfo::update(&music_dir, &queue[current_pos]);
+        let queue = mpc.run(|x| x.queue()).unwrap();
+        let song = queue.get(current_pos).map(SongInfo::update);
+        let showing_info = match song.is_some() {
+            true => current_pos,
+            false => usize::MAX
+        };
+        let song = song.unwrap_or_default();
 
         let switcher_cycle = match status.nextsong.is_some() {
             true => 2,
             false => 3
         };
         let switcher = utils::gen_switcher(switcher_cycle, &status, &queue);
-
-        let search_query = String::new();
-        let list = search::build_list(&search_query, &queue);
+        let exact_case = config.exact_case;
+        let keymap = Keymap::new(&config.keys);
 
         let data = Data {
             colours: Colours::default(),
-            update_timer: None,
+            config,
+            events: None,
             mpc,
             paths: (music_dir, song.filepath),
             queue,
+            library: None,
+            mode: Mode::Queue,
+            library_loading: false,
             current_pos,
             state: status.state,
-            showing_info: current_pos,
-            cover: None,
+            status,
+            showing_info,
+            covers: HashMap::new(),
+            placeholder: None,
+            loader: None,
             info_title: Some(song.title),
             info_artist: song.artist,
             info_album: song.album,
             info_duration: song.duration,
             info_date: song.date,
             elapsed,
+            elapsed_at: Instant::now(),
             duration,
             switcher,
             switcher_timer: None,
             switcher_cycle,
-            search_query,
-            list,
+            switcher_wake: None,
+            hold_switcher: false,
+            searcher: None,
+            search_query: String::new(),
+            search_error: None,
+            exact_case,
+            matching: Matching::Fuzzy,
+            search_generation: 0,
+            searching: false,
+            list: vec![],
+            list_anchor: None,
             selected: current_pos,
             selected_pos: current_pos,
+            error: None,
+            notice: None,
             interacted: false,
-            need_list_scroll: true
+            need_list_scroll: true,
+            marked: BTreeSet::new(),
+            search_history: history::searches(),
+            history_pos: None,
+            history_draft: String::new(),
+            query_recalled: false,
+            reverse_search: None,
+            keymap,
+            page_rows: 10
         };
         Self { data }
     }
 }
 
+#[derive(Default)]
 pub struct SongInfo {
-    pub cover:    Image,
     pub title:    String,
     pub artist:   Option<String>,
     pub album:    Option<String>,
@@ -106,14 +709,13 @@ pub struct SongInfo {
 }
 
 pub trait Update {
-    fn update(music_dir: &str, song: &Song) -> Self;
+    fn update(song: &Song) -> Self;
 }
 
 impl Update for SongInfo {
-    fn update(music_dir: &str, song: &Song) -> Self {
+    fn update(song: &Song) -> Self {
         let tags = &song.tags;
         Self {
-            cover:    images::get_cover(&(music_dir, &song.file)),
             title:    utils::gen_title(song),
             artist:   tags.get("Artist").map(|x| x.to_owned()),
             album:    tags.get("Album").map(|x| x.to_owned()),
@@ -128,9 +730,14 @@ impl Update for SongInfo {
 }
 
 pub struct SearchResult {
-    pub title: String,
-    pub pos:   usize,
-    pub ed:    usize
+    pub title:      String,
+    pub pos:        usize,
+    pub id:         u32,
+    pub ed:         usize,
+    // char positions in the title that matched the search
+    pub highlights: Vec<usize>,
+    // any other fields the search matched on (album, artist...) and their matched char positions
+    pub extra:      Vec<(String, Vec<usize>)>
 }
 
 #[allow(non_snake_case)]
@@ -0,0 +1,321 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::{anyhow, bail, Error, Result};
+use eframe::egui::{InputState, Key};
+use serde::Deserialize;
+
+// everything a key can do, the names are what config.yaml uses
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Play,
+    Append,
+    PlayNext,
+    MoveToTop,
+    MoveToBottom,
+    Remove,
+    Crop,
+    ToggleMark,
+    MarkAll,
+    Clear,
+    Quit,
+    TogglePause,
+    Stop,
+    Next,
+    Previous,
+    SeekBack,
+    SeekForward,
+    VolumeUp,
+    VolumeDown,
+    ToggleRepeat,
+    ToggleRandom,
+    ToggleSingle,
+    ToggleConsume,
+    CycleCrossfade,
+    CycleReplayGain,
+    SwitchMode,
+    ToggleCase,
+    CycleMatching,
+    HistoryBack,
+    HistoryForward,
+    ReverseSearch
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    #[default]
+    Default,
+    Vim,
+    Emacs
+}
+
+// a key and exactly the modifiers that have to be held with it, written like "ctrl+shift+enter"
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Binding {
+    key:   Key,
+    ctrl:  bool,
+    shift: bool,
+    alt:   bool
+}
+
+impl TryFrom<String> for Binding {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> { Self::try_from(s.as_str()) }
+}
+
+impl TryFrom<&str> for Binding {
+    type Error = Error;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        let lower = s.to_lowercase();
+        let mut parts = lower.split('+').collect::<Vec<_>>();
+        let key = parts.pop().unwrap_or_default();
+        let mut binding = Self {
+            key:   key_from_name(key).ok_or_else(|| anyhow!("unknown key '{}' in '{}'", key, s))?,
+            ctrl:  false,
+            shift: false,
+            alt:   false
+        };
+        for x in parts {
+            match x {
+                "ctrl" => binding.ctrl = true,
+                "shift" => binding.shift = true,
+                "alt" => binding.alt = true,
+                _ => bail!("unknown modifier '{}' in '{}'", x, s)
+            }
+        }
+        Ok(binding)
+    }
+}
+
+fn key_from_name(name: &str) -> Option<Key> {
+    let key = match name {
+        "up" => Key::ArrowUp,
+        "down" => Key::ArrowDown,
+        "left" => Key::ArrowLeft,
+        "right" => Key::ArrowRight,
+        "esc" | "escape" => Key::Escape,
+        "tab" => Key::Tab,
+        "backspace" => Key::Backspace,
+        "enter" | "return" => Key::Enter,
+        "space" => Key::Space,
+        "insert" => Key::Insert,
+        "delete" => Key::Delete,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "0" => Key::Num0,
+        "1" => Key::Num1,
+        "2" => Key::Num2,
+        "3" => Key::Num3,
+        "4" => Key::Num4,
+        "5" => Key::Num5,
+        "6" => Key::Num6,
+        "7" => Key::Num7,
+        "8" => Key::Num8,
+        "9" => Key::Num9,
+        "a" => Key::A,
+        "b" => Key::B,
+        "c" => Key::C,
+        "d" => Key::D,
+        "e" => Key::E,
+        "f" => Key::F,
+        "g" => Key::G,
+        "h" => Key::H,
+        "i" => Key::I,
+        "j" => Key::J,
+        "k" => Key::K,
+        "l" => Key::L,
+        "m" => Key::M,
+        "n" => Key::N,
+        "o" => Key::O,
+        "p" => Key::P,
+        "q" => Key::Q,
+        "r" => Key::R,
+        "s" => Key::S,
+        "t" => Key::T,
+        "u" => Key::U,
+        "v" => Key::V,
+        "w" => Key::W,
+        "x" => Key::X,
+        "y" => Key::Y,
+        "z" => Key::Z,
+        _ => return None
+    };
+    Some(key)
+}
+
+impl Binding {
+    fn held(&self, input: &InputState) -> bool {
+        let x = &input.modifiers;
+        x.ctrl == self.ctrl && x.shift == self.shift && x.alt == self.alt
+    }
+}
+
+// written the way config.yaml has it, egui's names only differ for the arrows and numbers
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let key = format!("{:?}", self.key).to_lowercase();
+        for (held, name) in [(self.ctrl, "ctrl+"), (self.shift, "shift+"), (self.alt, "alt+")] {
+            if held {
+                f.write_str(name)?
+            }
+        }
+        f.write_str(key.trim_start_matches("arrow").trim_start_matches("num"))
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut name = String::new();
+        for (i, c) in format!("{:?}", self).chars().enumerate() {
+            if c.is_uppercase() && i > 0 {
+                name.push('_')
+            }
+            name.push(c.to_ascii_lowercase())
+        }
+        f.write_str(&name)
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Keys {
+    pub preset:   Preset,
+    // replaces whatever the preset binds to an action
+    pub bindings: BTreeMap<Action, Vec<Binding>>
+}
+
+impl Keys {
+    // a key bound to two actions would do both at once
+    pub fn check(&self) -> Result<()> {
+        let pairs = self.pairs();
+        for (i, (binding, action)) in pairs.iter().enumerate() {
+            if let Some((_, x)) = pairs[..i].iter().find(|(x, _)| x == binding) {
+                bail!("'{}' is bound to both {} and {}", binding, x, action)
+            }
+        }
+        Ok(())
+    }
+
+    // the preset with the user's own bindings over it
+    fn pairs(&self) -> Vec<(Binding, Action)> {
+        let preset = match self.preset {
+            Preset::Default => &[],
+            Preset::Vim => VIM,
+            Preset::Emacs => EMACS
+        };
+        let mut actions = BTreeMap::new();
+        for (action, bindings) in DEFAULT.iter().chain(preset) {
+            let bindings = bindings.iter().map(|x| Binding::try_from(*x).unwrap());
+            actions.insert(*action, bindings.collect::<Vec<_>>());
+        }
+        for (action, bindings) in &self.bindings {
+            actions.insert(*action, bindings.clone());
+        }
+
+        let mut pairs = vec![];
+        for (action, bindings) in actions {
+            for x in bindings {
+                // the same key listed twice for one action would still only do it once
+                if !pairs.contains(&(x, action)) {
+                    pairs.push((x, action))
+                }
+            }
+        }
+        pairs
+    }
+}
+
+type Bindings = &'static [(Action, &'static [&'static str])];
+
+const DEFAULT: Bindings = &[
+    (Action::MoveUp, &["shift+tab"]),
+    (Action::MoveDown, &["tab"]),
+    (Action::PageUp, &["pageup"]),
+    (Action::PageDown, &["pagedown"]),
+    (Action::Top, &["ctrl+home"]),
+    (Action::Bottom, &["ctrl+end"]),
+    (Action::Play, &["enter"]),
+    (Action::Append, &["shift+enter"]),
+    (Action::PlayNext, &["ctrl+enter"]),
+    (Action::MoveToTop, &["alt+home"]),
+    (Action::MoveToBottom, &["alt+end"]),
+    (Action::Remove, &["ctrl+delete"]),
+    (Action::Crop, &["ctrl+shift+delete"]),
+    (Action::ToggleMark, &["insert", "alt+t"]),
+    (Action::MarkAll, &["alt+a"]),
+    (Action::Clear, &["ctrl+u"]),
+    (Action::Quit, &["escape"]),
+    (Action::TogglePause, &["ctrl+space"]),
+    (Action::Stop, &["ctrl+s"]),
+    (Action::Next, &["alt+right"]),
+    (Action::Previous, &["alt+left"]),
+    (Action::SeekBack, &["ctrl+left"]),
+    (Action::SeekForward, &["ctrl+right"]),
+    (Action::VolumeUp, &["alt+up"]),
+    (Action::VolumeDown, &["alt+down"]),
+    (Action::ToggleRepeat, &["alt+r"]),
+    (Action::ToggleRandom, &["alt+z"]),
+    (Action::ToggleSingle, &["alt+y"]),
+    (Action::ToggleConsume, &["alt+shift+r"]),
+    (Action::CycleCrossfade, &["alt+x"]),
+    (Action::CycleReplayGain, &["alt+g"]),
+    (Action::SwitchMode, &["ctrl+l"]),
+    (Action::ToggleCase, &["alt+c"]),
+    (Action::CycleMatching, &["alt+m"]),
+    (Action::HistoryBack, &["up", "ctrl+p"]),
+    (Action::HistoryForward, &["down", "ctrl+n"]),
+    (Action::ReverseSearch, &["ctrl+r"])
+];
+
+// only what differs from the default
+const VIM: Bindings = &[
+    (Action::MoveUp, &["shift+tab", "up", "ctrl+k"]),
+    (Action::MoveDown, &["tab", "down", "ctrl+j"]),
+    (Action::PageUp, &["pageup", "ctrl+b"]),
+    (Action::PageDown, &["pagedown", "ctrl+f"]),
+    (Action::HistoryBack, &["ctrl+p"]),
+    (Action::HistoryForward, &["ctrl+n"])
+];
+
+const EMACS: Bindings = &[
+    (Action::MoveUp, &["shift+tab", "up", "ctrl+p"]),
+    (Action::MoveDown, &["tab", "down", "ctrl+n"]),
+    (Action::PageUp, &["pageup", "alt+v"]),
+    (Action::Quit, &["escape", "ctrl+g"]),
+    (Action::HistoryBack, &["alt+p"]),
+    (Action::HistoryForward, &["alt+n"])
+];
+
+pub struct Keymap(Vec<(Binding, Action)>);
+
+impl Keymap {
+    // see Keys::check for what's rejected when config.yaml is loaded
+    pub fn new(keys: &Keys) -> Self { Self(keys.pairs()) }
+
+    // the actions whose keys went down this frame
+    pub fn pressed(&self, input: &InputState) -> Vec<Action> {
+        self.0
+            .iter()
+            .filter(|(x, _)| input.key_pressed(x.key) && x.held(input))
+            .map(|x| x.1)
+            .collect()
+    }
+
+    pub fn released(&self, input: &InputState, action: Action) -> bool {
+        self.0
+            .iter()
+            .any(|(x, y)| *y == action && input.key_released(x.key) && x.held(input))
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs;
+
+use anyhow::Result;
+use mpd::Song;
+use time::Duration;
+
+use super::{
+    cache,
+    conn::Settings,
+    proto::{Command, Raw}
+};
+
+#[derive(Clone, Copy)]
+pub enum Add {
+    Play,
+    Append,
+    Next
+}
+
+// listallinfo on a big library takes a while, so keep a copy on disk until mpd says the database changed
+pub fn load(settings: &Settings) -> Result<Vec<Song>> {
+    let mut raw = Raw::connect(settings)?;
+    let db_update = raw
+        .command("stats", &[])?
+        .into_iter()
+        .find(|(key, _)| key == "db_update")
+        .map(|(_, value)| value)
+        .unwrap_or_default();
+
+    let cache_dir = cache::root();
+    let cache_file = cache_dir.join("library");
+
+    if let Ok(x) = fs::read_to_string(&cache_file) {
+        if let Some((stamp, rest)) = x.split_once('\n') {
+            if stamp == db_update {
+                return Ok(parse(rest.lines().filter_map(|y| y.split_once(": "))))
+            }
+        }
+    }
+
+    // one listallinfo for the whole database can blow through mpd's max_output_buffer_size, so go one
+    // top level directory at a time
+    let mut pairs = vec![];
+    for (key, value) in raw.command("lsinfo", &[])? {
+        let more = match key == "directory" {
+            true => raw.command("listallinfo", &[&value])?,
+            false => vec![]
+        };
+        pairs.push((key, value));
+        pairs.extend(more)
+    }
+
+    let mut cache = [db_update, "\n".to_string()].join("");
+    for (key, value) in &pairs {
+        cache.push_str(key);
+        cache.push_str(": ");
+        cache.push_str(value);
+        cache.push('\n')
+    }
+    if !cache_dir.is_dir() {
+        fs::create_dir_all(&cache_dir)?
+    }
+    fs::write(cache_file, cache)?;
+
+    Ok(parse(
+        pairs.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    ))
+}
+
+fn parse<'a>(pairs: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<Song> {
+    let mut songs = vec![];
+    let mut song: Option<Song> = None;
+
+    for (key, value) in pairs {
+        match key {
+            "file" => {
+                songs.extend(song.take());
+                song = Some(Song {
+                    file: value.to_owned(),
+                    ..Default::default()
+                })
+            }
+            "directory" | "playlist" => songs.extend(song.take()),
+            _ => {
+                if let Some(x) = song.as_mut() {
+                    match key {
+                        "Title" => x.title = Some(value.to_owned()),
+                        "Name" => x.name = Some(value.to_owned()),
+                        "Time" => x.duration = value.parse().ok().map(Duration::seconds),
+                        "Last-Modified" | "Range" => {}
+                        _ => {
+                            x.tags.insert(key.to_owned(), value.to_owned());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    songs.extend(song);
+    songs
+}
+
+// the commands to add songs to the queue in the order given. with nothing playing there's no next, so
+// those go on the end. Add::Play plays the first by the id mpd gives back, see imp::activate
+pub fn add(songs: &[&Song], how: Add, current_pos: Option<usize>) -> Vec<Command> {
+    songs
+        .iter()
+        .enumerate()
+        .map(|(i, song)| match (how, current_pos) {
+            (Add::Next, Some(x)) => ("addid", vec![song.file.to_owned(), (x + 1 + i).to_string()]),
+            _ => ("addid", vec![song.file.to_owned()])
+        })
+        .collect()
+}
@@ -0,0 +1,129 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+use anyhow::{bail, Result};
+
+use super::conn::{self, Settings, Stream};
+
+// a command and its arguments, for command_list
+pub type Command = (&'static str, Vec<String>);
+
+// the mpd crate doesn't know about a few of the commands we need and keeps its protocol bits private, so
+// this is just enough of the protocol to send a command and read back the key/value pairs
+pub struct Raw {
+    stream: BufReader<Box<dyn Stream>>
+}
+
+impl Raw {
+    pub fn connect(settings: &Settings) -> Result<Self> {
+        let mut raw = Self {
+            stream: BufReader::new(conn::open(&settings.address)?)
+        };
+
+        let banner = raw.read_line()?;
+        if !banner.starts_with("OK MPD ") {
+            bail!("Unexpected greeting from mpd: {}", banner)
+        }
+        if let Some(x) = &settings.password {
+            raw.command("password", &[x])?;
+        }
+        Ok(raw)
+    }
+
+    pub fn command(&mut self, command: &str, args: &[&str]) -> Result<Vec<(String, String)>> {
+        self.send(command, args)?;
+
+        let mut pairs = vec![];
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok(pairs)
+            }
+            if let Some(x) = line.strip_prefix("ACK ") {
+                bail!("mpd: {}", x)
+            }
+            match line.split_once(": ") {
+                Some((key, value)) => pairs.push((key.to_owned(), value.to_owned())),
+                None => bail!("Unexpected reply from mpd: {}", line)
+            }
+        }
+    }
+
+    // albumart and readpicture send a file back a chunk at a time, each chunk after a "binary: <length>"
+    // line and asked for by offset. None when there's nothing to send
+    pub fn binary(&mut self, command: &str, uri: &str) -> Result<Option<Vec<u8>>> {
+        let mut data = vec![];
+        loop {
+            self.send(command, &[uri, &data.len().to_string()])?;
+
+            let (mut size, mut chunk) = (None, vec![]);
+            loop {
+                let line = self.read_line()?;
+                if line == "OK" {
+                    break
+                }
+                if let Some(x) = line.strip_prefix("ACK ") {
+                    bail!("mpd: {}", x)
+                }
+                match line.split_once(": ") {
+                    Some(("size", x)) => size = Some(x.parse::<usize>()?),
+                    Some(("binary", x)) => {
+                        chunk = vec![0; x.parse()?];
+                        self.stream.read_exact(&mut chunk)?;
+                        // the data is followed by a newline of its own
+                        self.read_line()?;
+                    }
+                    _ => {}
+                }
+            }
+
+            match size {
+                Some(x) if !chunk.is_empty() => {
+                    data.extend(chunk);
+                    if data.len() >= x {
+                        return Ok(Some(data))
+                    }
+                }
+                Some(_) => bail!("mpd stopped sending {} part way through", uri),
+                None => return Ok(None)
+            }
+        }
+    }
+
+    // everything goes in one command_list so it's a single round trip, and mpd stops at the first one that
+    // fails. the replies all come back together, in order
+    pub fn command_list(&mut self, commands: &[Command]) -> Result<Vec<(String, String)>> {
+        if commands.is_empty() {
+            return Ok(vec![])
+        }
+        self.send("command_list_begin", &[])?;
+        for (command, args) in commands {
+            self.send(command, &args.iter().map(|x| x.as_str()).collect::<Vec<_>>())?
+        }
+        self.command("command_list_end", &[])
+    }
+
+    fn send(&mut self, command: &str, args: &[&str]) -> Result<()> {
+        let mut line = command.to_owned();
+        for x in args {
+            line.push_str(" \"");
+            line.push_str(&x.replace('\\', "\\\\").replace('"', "\\\""));
+            line.push('"')
+        }
+        line.push('\n');
+
+        let stream = self.stream.get_mut();
+        stream.write_all(line.as_bytes())?;
+        Ok(stream.flush()?)
+    }
+
+    fn read_line(&mut self) -> Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line)? == 0 {
+            bail!("mpd closed the connection")
+        }
+        if line.ends_with('\n') {
+            line.pop();
+        }
+        Ok(line)
+    }
+}
@@ -0,0 +1,137 @@
+use super::proto::Command;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Edit {
+    // right after the current song
+    Next,
+    Top,
+    Bottom,
+    Remove,
+    // remove everything else
+    Crop
+}
+
+// the commands to apply an edit to the songs with these ids, in the order they're in the queue. by id so
+// a queue that changed since we last looked doesn't move the wrong song, each move is worked out against
+// a copy of the queue so it lands where it should after the ones before it
+pub fn edit(edit: Edit, ids: &[u32], queue: &[u32], current: Option<u32>) -> Vec<Command> {
+    let mut order = queue.to_vec();
+    let ids = queue
+        .iter()
+        .filter(|x| ids.contains(x))
+        .copied()
+        .collect::<Vec<_>>();
+    let mut commands = vec![];
+    match edit {
+        Edit::Next => {
+            let mut after = current;
+            for id in ids.into_iter().filter(|x| Some(*x) != current) {
+                let to = match after.and_then(|x| order.iter().position(|y| *y == x)) {
+                    // taking it out from before that song moves it up one
+                    Some(x) if position(&order, id) < x => x,
+                    Some(x) => x + 1,
+                    None => 0
+                };
+                shift(&mut order, &mut commands, id, to);
+                after = Some(id)
+            }
+        }
+        Edit::Top => ids
+            .into_iter()
+            .enumerate()
+            .for_each(|(i, id)| shift(&mut order, &mut commands, id, i)),
+        Edit::Bottom => {
+            let last = order.len().saturating_sub(1);
+            ids.into_iter()
+                .for_each(|id| shift(&mut order, &mut commands, id, last))
+        }
+        Edit::Remove => commands.extend(ids.into_iter().map(|id| ("deleteid", vec![id.to_string()]))),
+        Edit::Crop => {
+            let keep = ids.len();
+            ids.into_iter()
+                .enumerate()
+                .for_each(|(i, id)| shift(&mut order, &mut commands, id, i));
+            // an open ended range needs mpd 0.23
+            if keep > 0 && keep < order.len() {
+                commands.push(("delete", vec![
+                    [keep.to_string(), order.len().to_string()].join(":")
+                ]))
+            }
+        }
+    }
+    commands
+}
+
+fn position(order: &[u32], id: u32) -> usize { order.iter().position(|x| *x == id).unwrap_or(0) }
+
+fn shift(order: &mut Vec<u32>, commands: &mut Vec<Command>, id: u32, to: usize) {
+    let from = position(order, id);
+    if from == to {
+        return
+    }
+    order.remove(from);
+    order.insert(to, id);
+    commands.push(("moveid", vec![id.to_string(), to.to_string()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUEUE: [u32; 6] = [1, 2, 3, 4, 5, 6];
+
+    fn moveid(id: u32, to: usize) -> Command { ("moveid", vec![id.to_string(), to.to_string()]) }
+
+    #[test]
+    fn next_goes_after_the_current_song_in_queue_order() {
+        assert_eq!(edit(Edit::Next, &[5, 3], &QUEUE, Some(2)), vec![moveid(5, 3)]);
+        assert_eq!(edit(Edit::Next, &[1, 6], &QUEUE, Some(4)), vec![
+            moveid(1, 3),
+            moveid(6, 4)
+        ]);
+    }
+
+    #[test]
+    fn next_leaves_the_current_song_alone() {
+        assert_eq!(edit(Edit::Next, &[2, 6], &QUEUE, Some(2)), vec![moveid(6, 2)]);
+    }
+
+    #[test]
+    fn next_with_nothing_playing_goes_to_the_top() {
+        assert_eq!(edit(Edit::Next, &[3, 5], &QUEUE, None), vec![
+            moveid(3, 0),
+            moveid(5, 1)
+        ]);
+    }
+
+    #[test]
+    fn top_and_bottom_keep_the_order() {
+        assert_eq!(edit(Edit::Top, &[5, 3], &QUEUE, Some(2)), vec![
+            moveid(3, 0),
+            moveid(5, 1)
+        ]);
+        assert_eq!(edit(Edit::Bottom, &[4, 2], &QUEUE, Some(2)), vec![
+            moveid(2, 5),
+            moveid(4, 5)
+        ]);
+    }
+
+    #[test]
+    fn remove_by_id() {
+        let commands = edit(Edit::Remove, &[4, 2, 9], &QUEUE, Some(2));
+        assert_eq!(commands, vec![
+            ("deleteid", vec!["2".to_string()]),
+            ("deleteid", vec!["4".to_string()]),
+        ]);
+    }
+
+    #[test]
+    fn crop_moves_to_the_top_and_deletes_the_rest() {
+        assert_eq!(edit(Edit::Crop, &[4, 2], &QUEUE, Some(2)), vec![
+            moveid(2, 0),
+            moveid(4, 1),
+            ("delete", vec!["2:6".to_string()]),
+        ]);
+        assert_eq!(edit(Edit::Crop, &QUEUE, &QUEUE, Some(2)), vec![]);
+    }
+}
@@ -1,73 +1,503 @@
+mod fuzzy;
+mod index;
+mod query;
+
+use std::{
+    collections::HashMap,
+    iter::once,
+    slice,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Sender},
+        Arc
+    },
+    thread
+};
+
+use anyhow::{anyhow, bail, Result};
 use asearch::Asearch;
 use edit_distance::edit_distance;
+use eframe::epi::Frame;
 use mpd::Song;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
 
-use super::{imp::SearchResult, utils};
+pub use self::query::Matching;
+use self::{
+    index::{fold, Entry, Index, Text},
+    query::{Field, Term}
+};
+use super::{
+    config::Config,
+    history,
+    imp::{Mode, SearchResult},
+    worker::Event
+};
 
-pub fn build_list(q: &str, queue: &[Song]) -> Vec<SearchResult> {
-    let mut list = vec![];
-    match q.is_empty() {
-        true => {
-            for (i, song) in queue.iter().enumerate() {
-                list.push(SearchResult {
-                    title: utils::gen_title(song),
-                    pos:   i,
-                    ed:    0
-                })
-            }
+// how results are matched and ranked, classic is the original asearch + edit distance
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scorer {
+    #[default]
+    Fuzzy,
+    Classic
+}
+
+// how much a plain word matching each field counts, from 10 (as good as the title) down to 1, 0 means
+// only match it with field:word
+#[derive(Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    pub title:       u8,
+    pub artist:      u8,
+    pub albumartist: u8,
+    pub album:       u8,
+    pub composer:    u8,
+    pub performer:   u8,
+    pub genre:       u8,
+    pub date:        u8,
+    pub comment:     u8,
+    pub track:       u8,
+    pub disc:        u8,
+    pub file:        u8
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            title:       10,
+            artist:      6,
+            albumartist: 6,
+            album:       8,
+            composer:    5,
+            performer:   4,
+            genre:       3,
+            date:        3,
+            comment:     1,
+            track:       0,
+            disc:        0,
+            file:        1
         }
+    }
+}
 
-        false => {
-            let query = Asearch::new([" ", q, " "].join(""));
-            let max_ed = 99;
+impl Weights {
+    fn get(&self, field: Field) -> u8 {
+        match field {
+            Field::Title => self.title,
+            Field::Artist => self.artist,
+            Field::AlbumArtist => self.albumartist,
+            Field::Album => self.album,
+            Field::Composer => self.composer,
+            Field::Performer => self.performer,
+            Field::Genre => self.genre,
+            Field::Date => self.date,
+            Field::Comment => self.comment,
+            Field::Track => self.track,
+            Field::Disc => self.disc,
+            Field::File => self.file
+        }
+    }
 
-            for (i, song) in queue.iter().enumerate() {
-                let match_title = match song.title.is_some() {
-                    true => song.title.as_ref().unwrap(),
-                    false => &song.file
-                };
-                let title = utils::gen_title(song);
+    // the fields plain words are matched against, best first, with what a match there adds to the cost.
+    // the defaults keep the old offsets of title +0, album +100 and artist +200
+    fn penalties(&self) -> Vec<(Field, usize)> {
+        let mut fields = Field::ALL
+            .into_iter()
+            .filter(|x| self.get(*x) > 0)
+            .map(|x| (x, (10 - self.get(x).min(10) as usize) * 50))
+            .collect::<Vec<_>>();
+        fields.sort_by_key(|x| x.1);
+        fields
+    }
+}
+
+struct Query {
+    generation: usize,
+    text:       String,
+    mode:       Mode,
+    exact_case: bool,
+    // for plain words without a =/regex/ prefix
+    matching:   Matching
+}
+
+enum Request {
+    Query(Query),
+    Queue(Vec<Song>, usize),
+    Library(Index)
+}
+
+// searching 100k songs on every keystroke is too slow for the ui thread, so a worker owns an index of
+// the queue and library and only ever works on the newest query
+#[derive(Clone)]
+pub struct Searcher {
+    requests:      Sender<Request>,
+    latest:        Arc<AtomicUsize>,
+    transliterate: bool
+}
+
+// the last query that ran to completion and its matches, a query that only narrows it down can start
+// from those rather than every song
+struct Last {
+    mode:       Mode,
+    exact_case: bool,
+    terms:      Vec<Term>,
+    matches:    Vec<usize>
+}
+
+impl Searcher {
+    pub fn spawn(config: &Config, tx: Sender<Event>, frame: Frame) -> Self {
+        let (requests, rx) = channel();
+        let latest = Arc::new(AtomicUsize::new(0));
+        let (scorer, transliterate) = (config.scorer, config.transliterate);
+        let fields = config.weights.penalties();
 
-                if query.find(&*match_title, 0) {
-                    let ed = edit_distance(q, match_title);
-                    if ed < max_ed {
-                        list.push(SearchResult { title, pos: i, ed })
+        let worker_latest = latest.clone();
+        thread::spawn(move || {
+            let mut queue = Index::new(&[], transliterate);
+            let mut library = Index::new(&[], transliterate);
+            let mut last: Option<Last> = None;
+            let frecency = history::frecency();
+
+            while let Ok(request) = rx.recv() {
+                let mut query = None;
+                for x in once(request).chain(rx.try_iter()) {
+                    match x {
+                        Request::Query(x) => query = Some(x),
+                        Request::Queue(changes, len) => {
+                            queue.update(&changes, len);
+                            last = None
+                        }
+                        Request::Library(x) => {
+                            library = x;
+                            last = None
+                        }
                     }
                 }
-                else {
-                    let tags = &song.tags;
-                    let album = tags.get("Album").map(|x| x.to_owned());
-                    let artist = tags.get("Artist").map(|x| x.to_owned());
-
-                    if let Some(x) = album {
-                        if query.find(&x, 0) {
-                            let ed = edit_distance(q, &x);
-                            if ed < max_ed {
-                                list.push(SearchResult {
-                                    title,
-                                    pos: i,
-                                    ed: ed + 100
-                                });
-                                continue
-                            }
+
+                let Query {
+                    generation,
+                    text,
+                    mode,
+                    exact_case,
+                    matching
+                } = match query {
+                    Some(x) => x,
+                    None => continue
+                };
+                let index = match mode {
+                    Mode::Queue => &queue,
+                    Mode::Library => &library
+                };
+                let parsed = query::parse(&text, matching).and_then(|terms| {
+                    let matchers = terms
+                        .iter()
+                        .map(|x| Matcher::new(x, scorer, exact_case, transliterate))
+                        .collect::<Result<Vec<_>>>()?;
+                    Ok((terms, matchers))
+                });
+                let (terms, matchers) = match parsed {
+                    Ok(x) => x,
+                    Err(e) => {
+                        if tx.send(Event::Results(generation, Err(e))).is_err() {
+                            return
                         }
+                        frame.request_repaint();
+                        continue
                     }
-                    if let Some(x) = artist {
-                        if query.find(&x, 0) {
-                            let ed = edit_distance(q, &x);
-                            if ed < max_ed {
-                                list.push(SearchResult {
-                                    title,
-                                    pos: i,
-                                    ed: ed + 200
-                                });
-                            }
-                        }
+                };
+
+                // classic drops terms much shorter than the field by edit distance, so a longer term can
+                // match songs the shorter one didn't and narrowing down doesn't hold for it
+                let candidates = match &last {
+                    Some(x)
+                        if scorer != Scorer::Classic
+                            && x.mode == mode
+                            && x.exact_case == exact_case
+                            && refines(&x.terms, &terms) =>
+                    {
+                        Some(x.matches.to_owned())
+                    }
+                    _ => None
+                };
+                let cancelled = || worker_latest.load(Ordering::Relaxed) != generation;
+                if let Some(list) = run(index, &matchers, &fields, &frecency, candidates, cancelled) {
+                    let mut matches = list.iter().map(|x| x.pos).collect::<Vec<_>>();
+                    matches.sort_unstable();
+                    last = Some(Last {
+                        mode,
+                        exact_case,
+                        terms,
+                        matches
+                    });
+
+                    if tx.send(Event::Results(generation, Ok(list))).is_err() {
+                        return
                     }
+                    frame.request_repaint()
+                }
+            }
+        });
+
+        Self {
+            requests,
+            latest,
+            transliterate
+        }
+    }
+
+    // the results come back as an Event::Results with the generation returned here, anything still
+    // running for an older query gets abandoned
+    pub fn query(&self, q: &str, mode: Mode, exact_case: bool, matching: Matching) -> usize {
+        let generation = self.latest.fetch_add(1, Ordering::Relaxed) + 1;
+        let _ = self.requests.send(Request::Query(Query {
+            generation,
+            text: q.to_owned(),
+            mode,
+            exact_case,
+            matching
+        }));
+        generation
+    }
+
+    pub fn update_queue(&self, changes: Vec<Song>, len: usize) {
+        let _ = self.requests.send(Request::Queue(changes, len));
+    }
+
+    // builds the index on the calling thread, so call it from somewhere other than the ui
+    pub fn set_library(&self, songs: &[Song]) {
+        let _ = self
+            .requests
+            .send(Request::Library(Index::new(songs, self.transliterate)));
+    }
+}
+
+// whether everything new matches is guaranteed to have matched old too, e.g. another term or more letters
+// on the end of one. negated terms have to stay the same as excluding less lets more songs back in, and
+// there's no telling with a regex
+fn refines(old: &[Term], new: &[Term]) -> bool {
+    old.len() <= new.len()
+        && old.iter().zip(new).all(|(a, b)| {
+            a.field == b.field
+                && a.phrase == b.phrase
+                && a.negated == b.negated
+                && a.matching == b.matching
+                && match a.negated || a.matching == Matching::Regex {
+                    true => a.text == b.text,
+                    false => b.text.starts_with(&a.text)
                 }
+        })
+}
+
+// one way of matching a term against a field, lower cost is better and positions are chars of Text::norm
+trait Match {
+    fn find(&self, s: &Text) -> Option<(usize, Vec<usize>)>;
+}
+
+struct FuzzyMatch {
+    text:       Text,
+    exact_case: bool
+}
+
+impl Match for FuzzyMatch {
+    fn find(&self, s: &Text) -> Option<(usize, Vec<usize>)> { fuzzy::find(s, &self.text, self.exact_case) }
+}
+
+// the original asearch + edit distance. asearch goes a char at a time, so it gets the folded text like the
+// others, but it always ignores case and only knows ascii case at that
+struct ClassicMatch {
+    text:       Text,
+    pattern:    Asearch,
+    phrase:     bool,
+    exact_case: bool
+}
+
+impl Match for ClassicMatch {
+    fn find(&self, s: &Text) -> Option<(usize, Vec<usize>)> {
+        let max_ed = 99;
+        let chars = find_chars(s, &self.text, self.exact_case);
+        let found = match self.phrase {
+            true => chars.is_some(),
+            false => self.pattern.find(s.norm(), 0)
+        };
+        match found {
+            true => Some(edit_distance(self.text.norm(), s.norm()))
+                .filter(|x| *x < max_ed)
+                .map(|x| (x, chars.unwrap_or_default())),
+            false => None
+        }
+    }
+}
+
+// a plain substring, still ranked by where it lands (word starts etc.)
+struct ExactMatch {
+    text:       Text,
+    exact_case: bool
+}
+
+impl Match for ExactMatch {
+    fn find(&self, s: &Text) -> Option<(usize, Vec<usize>)> {
+        find_chars(s, &self.text, self.exact_case).map(|x| (fuzzy::cost(s, &self.text, &x), x))
+    }
+}
+
+// earlier matches rank higher. against the raw text, normalising would turn fullwidth brackets and the like
+// into regex syntax
+struct RegexMatch {
+    pattern: Regex
+}
+
+impl Match for RegexMatch {
+    fn find(&self, s: &Text) -> Option<(usize, Vec<usize>)> {
+        let found = self.pattern.find(&s.raw)?;
+        let start = s.raw[..found.start()].chars().count();
+        let end = start + found.as_str().chars().count();
+        Some((start, s.norm_positions(start..end)))
+    }
+}
+
+struct Matcher {
+    term:    Term,
+    matcher: Box<dyn Match>
+}
+
+impl Matcher {
+    fn new(term: &Term, scorer: Scorer, exact_case: bool, transliterate: bool) -> Result<Self> {
+        // the term's text through the same normalisation as the index
+        let text = Text::new(&term.text, transliterate);
+        // e.g. a lone accent, there's nothing left to match once it's normalised
+        if text.norm().is_empty() {
+            bail!("nothing to search for in '{}'", term.text)
+        }
+        let matcher: Box<dyn Match> = match (term.matching, term.phrase, scorer) {
+            (Matching::Regex, ..) => Box::new(RegexMatch {
+                pattern: RegexBuilder::new(&term.text)
+                    .case_insensitive(!exact_case)
+                    .build()
+                    .map_err(|e| anyhow!("invalid regex: {}", e))?
+            }),
+            (Matching::Exact, ..) | (Matching::Fuzzy, true, Scorer::Fuzzy) => {
+                Box::new(ExactMatch { text, exact_case })
             }
-            list.sort_unstable_by(|a, b| a.ed.cmp(&b.ed));
+            (Matching::Fuzzy, false, Scorer::Fuzzy) => Box::new(FuzzyMatch { text, exact_case }),
+            (Matching::Fuzzy, phrase, Scorer::Classic) => Box::new(ClassicMatch {
+                pattern: Asearch::new([" ", text.norm(), " "].join("")),
+                text,
+                phrase,
+                exact_case
+            })
+        };
+        Ok(Self {
+            term: term.to_owned(),
+            matcher
+        })
+    }
+}
+
+// None if a newer query came in while this one was running
+fn run(
+    index: &Index, matchers: &[Matcher], fields: &[(Field, usize)], frecency: &HashMap<String, f64>,
+    candidates: Option<Vec<usize>>, cancelled: impl Fn() -> bool
+) -> Option<Vec<SearchResult>> {
+    // songs played often and lately get up to this much taken off, about as much as two steps of field
+    // weight. everything gets it added first so a perfect match can still be lifted above another one.
+    // nothing moves without a query though, the queue stays in order
+    let max_bonus = 100;
+    let bonus = |x: &Entry| match matchers.is_empty() {
+        true => 0,
+        false => {
+            let score = x
+                .get(Field::File)
+                .and_then(|y| frecency.get(&y.raw))
+                .unwrap_or(&0.0);
+            max_bonus - (score * 20.0).min(max_bonus as f64) as usize
         }
+    };
+
+    let candidates = candidates.unwrap_or_else(|| (0..index.entries.len()).collect());
+    let mut list = vec![];
+    for (n, i) in candidates.into_iter().enumerate() {
+        if n % 1024 == 0 && cancelled() {
+            return None
+        }
+
+        let entry = &index.entries[i];
+        if let Some((ed, mut found)) = score(matchers, fields, entry) {
+            let highlights = match found.iter().position(|x| x.0 == Field::Title) {
+                Some(x) => found.remove(x).2,
+                None => vec![]
+            };
+            list.push(SearchResult {
+                title: entry.title().to_owned(),
+                pos: i,
+                id: entry.id,
+                ed: ed + bonus(entry),
+                highlights,
+                extra: found.into_iter().map(|x| (x.1.to_owned(), x.2)).collect()
+            })
+        }
+    }
+    // stable so equally good matches keep their queue/library order
+    list.sort_by_key(|x| x.ed);
+    Some(list)
+}
+
+// every field that took part in a match, with the matched character positions within it
+type Found<'a> = Vec<(Field, &'a str, Vec<usize>)>;
+
+// every term has to match (or not match when negated), lower is better. plain words count wherever they
+// match best once the field's weight is taken into account
+fn score<'a>(
+    matchers: &[Matcher], fields: &[(Field, usize)], entry: &'a Entry
+) -> Option<(usize, Found<'a>)> {
+    let mut total = 0;
+    let mut found: Found = vec![];
+
+    for x in matchers {
+        let only = x.term.field.map(|y| (y, 0));
+        let fields = match &only {
+            Some(y) => slice::from_ref(y),
+            None => fields
+        };
+        let hit = fields
+            .iter()
+            .filter_map(|&(field, offset)| {
+                let value = entry.get(field)?;
+                x.matcher
+                    .find(value)
+                    .map(|(ed, chars)| (field, value.raw.as_str(), ed + offset, value.raw_positions(chars)))
+            })
+            .min_by_key(|y| y.2);
+
+        match (hit, x.term.negated) {
+            (Some((field, value, ed, chars)), false) => {
+                total += ed;
+                match found.iter_mut().find(|y| y.0 == field) {
+                    Some(y) => y.2.extend(chars),
+                    None => found.push((field, value, chars))
+                }
+            }
+            (None, true) => {}
+            _ => return None
+        }
+    }
+    Some((total, found))
+}
+
+// substring search, in chars rather than bytes so the ui can highlight them
+fn find_chars(s: &Text, needle: &Text, exact_case: bool) -> Option<Vec<usize>> {
+    if exact_case {
+        let start = s.norm().find(needle.norm())?;
+        let start = s.norm()[..start].chars().count();
+        return Some((start..start + needle.norm().chars().count()).collect())
+    }
+
+    let hay = s.norm().chars().map(fold).collect::<Vec<_>>();
+    let pat = needle.norm().chars().map(fold).collect::<Vec<_>>();
+    if pat.len() > hay.len() {
+        return None
     }
-    list
+    (0..=hay.len() - pat.len())
+        .find(|&i| hay[i..].starts_with(&pat))
+        .map(|i| (i..i + pat.len()).collect())
 }
@@ -0,0 +1,237 @@
+use super::index::{fold, Text};
+
+// an fzf style scorer, pattern chars have to appear in order and the best alignment (smith-waterman
+// with affine gaps) wins. matches at the start of words, runs of consecutive matches and matching case
+// all earn a bonus, gaps between matches cost a little
+const SCORE_MATCH: i64 = 16;
+const GAP_START: i64 = -3;
+const GAP_EXTENSION: i64 = -1;
+const BONUS_BOUNDARY: i64 = SCORE_MATCH / 2;
+const BONUS_BOUNDARY_WHITE: i64 = BONUS_BOUNDARY + 2;
+const BONUS_BOUNDARY_DELIMITER: i64 = BONUS_BOUNDARY + 1;
+const BONUS_NON_WORD: i64 = SCORE_MATCH / 2;
+const BONUS_CAMEL_123: i64 = BONUS_BOUNDARY + GAP_EXTENSION;
+const BONUS_CONSECUTIVE: i64 = -(GAP_START + GAP_EXTENSION);
+const BONUS_FIRST_CHAR_MULTIPLIER: i64 = 2;
+const BONUS_CASE: i64 = 2;
+
+// the most a single pattern char can earn, used to turn a score into a cost where 0 is a perfect match
+const PERFECT_FIRST: i64 = SCORE_MATCH + BONUS_BOUNDARY_WHITE * BONUS_FIRST_CHAR_MULTIPLIER + BONUS_CASE;
+const PERFECT: i64 = SCORE_MATCH + BONUS_BOUNDARY_WHITE + BONUS_CASE;
+
+const NONE: i64 = i64::MIN / 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    White,
+    Delimiter,
+    NonWord,
+    Lower,
+    Upper,
+    Letter,
+    Number
+}
+
+fn class(c: char) -> Class {
+    match c {
+        x if x.is_whitespace() => Class::White,
+        '/' | ',' | ':' | ';' | '|' | '-' | '_' | '.' => Class::Delimiter,
+        x if x.is_lowercase() => Class::Lower,
+        x if x.is_uppercase() => Class::Upper,
+        x if x.is_alphabetic() => Class::Letter,
+        x if x.is_numeric() => Class::Number,
+        _ => Class::NonWord
+    }
+}
+
+fn bonus(prev: Class, curr: Class) -> i64 {
+    let word = |x: Class| matches!(x, Class::Lower | Class::Upper | Class::Letter | Class::Number);
+    match (prev, curr) {
+        (Class::White, x) if word(x) => BONUS_BOUNDARY_WHITE,
+        (Class::Delimiter, x) if word(x) => BONUS_BOUNDARY_DELIMITER,
+        (Class::NonWord, x) if word(x) => BONUS_BOUNDARY,
+        (Class::Lower, Class::Upper) => BONUS_CAMEL_123,
+        (x, Class::Number) if x != Class::Number => BONUS_CAMEL_123,
+        (_, Class::White) => BONUS_BOUNDARY_WHITE,
+        (_, Class::NonWord | Class::Delimiter) => BONUS_NON_WORD,
+        _ => 0
+    }
+}
+
+// bonus for every char in the text, the start counts as following whitespace
+fn bonuses(text: &[char]) -> Vec<i64> {
+    let mut prev = Class::White;
+    text.iter()
+        .map(|x| {
+            let curr = class(*x);
+            let y = bonus(prev, curr);
+            prev = curr;
+            y
+        })
+        .collect()
+}
+
+fn case_bonus(p: char, t: char) -> i64 {
+    match p == t {
+        true => BONUS_CASE,
+        false => 0
+    }
+}
+
+fn to_cost(score: i64, pattern_len: usize) -> usize {
+    let perfect = PERFECT_FIRST + PERFECT * (pattern_len as i64 - 1);
+    (perfect - score).max(0) as usize
+}
+
+// lower is better, along with the (normalised) char positions in s that matched
+pub fn find(s: &Text, pattern: &Text, exact_case: bool) -> Option<(usize, Vec<usize>)> {
+    let fold = |x: char| match exact_case {
+        true => x,
+        false => fold(x)
+    };
+
+    // cheap check before doing the full alignment
+    let mut rest = s.norm().chars();
+    if !pattern.norm().chars().all(|p| rest.any(|t| fold(t) == fold(p))) {
+        return None
+    }
+
+    let text = s.norm().chars().collect::<Vec<_>>();
+    let folded = text.iter().map(|x| fold(*x)).collect::<Vec<_>>();
+    let pat = pattern.norm().chars().collect::<Vec<_>>();
+    let pat_folded = pat.iter().map(|x| fold(*x)).collect::<Vec<_>>();
+    let (n, m) = (text.len(), pat.len());
+    if m == 0 {
+        return None
+    }
+
+    let bonus = bonuses(&text);
+
+    // score of the best alignment of pat[..=i] ending with pat[i] on text[j], the bonus of the run of
+    // consecutive matches it's part of and where pat[i - 1] was matched to get there
+    let mut score = vec![NONE; m * n];
+    let mut chain = vec![0; m * n];
+    let mut from = vec![0; m * n];
+
+    for (j, t) in text.iter().enumerate() {
+        if folded[j] == pat_folded[0] {
+            score[j] = SCORE_MATCH + bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER + case_bonus(pat[0], *t);
+            chain[j] = bonus[j]
+        }
+    }
+
+    for (i, p) in pat.iter().enumerate().skip(1) {
+        let (row, prev) = (i * n, (i - 1) * n);
+        let mut gap = (NONE, 0);
+        for j in i..n {
+            if j >= 2 {
+                gap.0 += GAP_EXTENSION;
+                if score[prev + j - 2] + GAP_START > gap.0 {
+                    gap = (score[prev + j - 2] + GAP_START, j - 2)
+                }
+            }
+            if folded[j] != pat_folded[i] {
+                continue
+            }
+
+            let matched = SCORE_MATCH + case_bonus(*p, text[j]);
+            let run_bonus = bonus[j].max(chain[prev + j - 1]).max(BONUS_CONSECUTIVE);
+            let consecutive = score[prev + j - 1] + matched + run_bonus;
+            let gapped = gap.0 + matched + bonus[j];
+
+            match consecutive >= gapped {
+                true => {
+                    score[row + j] = consecutive;
+                    chain[row + j] = run_bonus;
+                    from[row + j] = j - 1
+                }
+                false => {
+                    score[row + j] = gapped;
+                    chain[row + j] = bonus[j];
+                    from[row + j] = gap.1
+                }
+            }
+        }
+    }
+
+    let last = (m - 1) * n;
+    let (end, best) = (0..n)
+        .map(|j| (j, score[last + j]))
+        .max_by_key(|x| x.1)
+        .filter(|x| x.1 > NONE / 2)?;
+
+    let mut positions = vec![end; m];
+    for i in (1..m).rev() {
+        positions[i - 1] = from[i * n + positions[i]]
+    }
+    Some((to_cost(best, m), positions))
+}
+
+// the cost of an already known set of positions, for phrases which have to match as a whole
+pub fn cost(s: &Text, pattern: &Text, positions: &[usize]) -> usize {
+    let text = s.norm().chars().collect::<Vec<_>>();
+    let pat = pattern.norm().chars().collect::<Vec<_>>();
+    let bonus = bonuses(&text);
+
+    let mut score = 0;
+    let mut run_bonus = 0;
+    for (i, (p, j)) in pat.iter().zip(positions).enumerate() {
+        let (j, t) = (*j, text[*j]);
+        score += SCORE_MATCH + case_bonus(*p, t);
+        match i {
+            0 => {
+                score += bonus[j] * BONUS_FIRST_CHAR_MULTIPLIER;
+                run_bonus = bonus[j]
+            }
+            _ => {
+                run_bonus = bonus[j].max(run_bonus).max(BONUS_CONSECUTIVE);
+                score += run_bonus
+            }
+        }
+    }
+    to_cost(score, pat.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find_cost(s: &str, pattern: &str) -> Option<usize> { find_in(s, pattern, false).map(|x| x.0) }
+
+    fn find_in(s: &str, pattern: &str, exact_case: bool) -> Option<(usize, Vec<usize>)> {
+        find(&Text::new(s, false), &Text::new(pattern, false), exact_case)
+    }
+
+    #[test]
+    fn chars_have_to_be_in_order() {
+        assert_eq!(find_in("Hello World", "hw", false).map(|x| x.1), Some(vec![0, 6]));
+        assert_eq!(find_cost("Hello World", "wh"), None);
+        assert_eq!(find_cost("abc", "abd"), None);
+    }
+
+    #[test]
+    fn empty_pattern_matches_nothing() {
+        assert_eq!(find_cost("abc", ""), None);
+        assert_eq!(find_cost("", "a"), None);
+    }
+
+    #[test]
+    fn better_alignments_cost_less() {
+        assert_eq!(find_cost("abc", "abc"), Some(0));
+        assert!(find_cost("abcx", "abc").unwrap() < find_cost("axbxc", "abc").unwrap());
+        assert!(find_cost("foo bar", "ba").unwrap() < find_cost("foobar", "ba").unwrap());
+    }
+
+    #[test]
+    fn case() {
+        assert!(find_in("ABC", "abc", true).is_none());
+        assert!(find_in("ABC", "abc", false).is_some());
+        assert!(find_cost("abc", "abc").unwrap() < find_cost("ABC", "abc").unwrap());
+    }
+
+    #[test]
+    fn diacritics_are_ignored() {
+        assert!(find_cost("Café", "cafe").is_some());
+        assert!(find_cost("cafe", "Cafe\u{301}").is_some());
+    }
+}
@@ -0,0 +1,138 @@
+use std::ops::Range;
+
+use deunicode::deunicode_char;
+use mpd::Song;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use super::query::Field;
+use crate::app::utils;
+
+// a field value as shown, and normalised for the matchers to compare against: NFKD (which also folds
+// fullwidth and halfwidth forms), without diacritics and optionally transliterated to ascii. case is
+// left alone so exact case matching still works, see fold
+pub struct Text {
+    pub raw: String,
+    // None when it would be the same as raw, which is most of the time
+    norm:    Option<String>,
+    // which raw char each norm char came from, None when they line up one to one
+    map:     Option<Vec<u32>>
+}
+
+impl Text {
+    pub fn new(s: &str, transliterate: bool) -> Self {
+        let mut text = Self {
+            raw:  s.to_owned(),
+            norm: None,
+            map:  None
+        };
+        if s.is_ascii() {
+            return text
+        }
+
+        let mut norm = String::with_capacity(s.len());
+        let mut map = vec![];
+        for (i, c) in s.chars().enumerate() {
+            for x in c.nfkd().filter(|x| !is_combining_mark(*x)) {
+                match deunicode_char(x).filter(|_| transliterate && !x.is_ascii()) {
+                    Some(y) => y.chars().for_each(|z| {
+                        norm.push(z);
+                        map.push(i as u32)
+                    }),
+                    None => {
+                        norm.push(x);
+                        map.push(i as u32)
+                    }
+                }
+            }
+        }
+
+        if map.iter().enumerate().any(|(i, x)| i != *x as usize) || map.len() != s.chars().count() {
+            text.map = Some(map)
+        }
+        if norm != s {
+            text.norm = Some(norm)
+        }
+        text
+    }
+
+    pub fn norm(&self) -> &str { self.norm.as_deref().unwrap_or(&self.raw) }
+
+    // matchers work on norm, the ui highlights raw
+    pub fn raw_positions(&self, mut chars: Vec<usize>) -> Vec<usize> {
+        if let Some(x) = &self.map {
+            chars = chars.into_iter().map(|y| x[y] as usize).collect();
+            chars.dedup()
+        }
+        chars
+    }
+
+    // the other way, the norm chars that came from a run of raw ones
+    pub fn norm_positions(&self, raw: Range<usize>) -> Vec<usize> {
+        match &self.map {
+            Some(x) => (0..x.len()).filter(|y| raw.contains(&(x[*y] as usize))).collect(),
+            None => raw.collect()
+        }
+    }
+}
+
+// case insensitive comparisons go through this, a char at a time so positions don't move
+pub fn fold(c: char) -> char { c.to_lowercase().next().unwrap_or(c) }
+
+// everything the matchers look at for a song, worked out once rather than on every keystroke
+pub struct Entry {
+    pub id: u32,
+    fields: Vec<(Field, Text)>
+}
+
+impl Entry {
+    fn new(song: &Song, transliterate: bool) -> Self {
+        let title = utils::gen_title(song);
+        Self {
+            id:     song.place.map(|x| x.id.0).unwrap_or_default(),
+            fields: Field::ALL
+                .into_iter()
+                .filter_map(|field| {
+                    let value = match field {
+                        Field::Title => Some(&title),
+                        Field::File => Some(&song.file),
+                        x => song.tags.get(x.tag())
+                    };
+                    value.map(|x| (field, Text::new(x, transliterate)))
+                })
+                .collect()
+        }
+    }
+
+    pub fn get(&self, field: Field) -> Option<&Text> {
+        self.fields.iter().find(|x| x.0 == field).map(|x| &x.1)
+    }
+
+    pub fn title(&self) -> &str { &self.get(Field::Title).unwrap().raw }
+}
+
+// one entry per song, in the same order as the songs it was built from
+pub struct Index {
+    pub entries:   Vec<Entry>,
+    transliterate: bool
+}
+
+impl Index {
+    pub fn new(songs: &[Song], transliterate: bool) -> Self {
+        Self {
+            entries: songs.iter().map(|x| Entry::new(x, transliterate)).collect(),
+            transliterate
+        }
+    }
+
+    // the same plchanges update the ui does to its copy of the queue
+    pub fn update(&mut self, changes: &[Song], len: usize) {
+        self.entries.truncate(len);
+        for song in changes {
+            match song.place.map(|x| x.pos as usize) {
+                Some(x) if x < self.entries.len() => self.entries[x] = Entry::new(song, self.transliterate),
+                Some(_) => self.entries.push(Entry::new(song, self.transliterate)),
+                None => {}
+            }
+        }
+    }
+}
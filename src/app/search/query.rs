@@ -0,0 +1,286 @@
+use std::{iter::Peekable, str::Chars};
+
+use anyhow::{bail, Result};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Field {
+    Title,
+    Artist,
+    AlbumArtist,
+    Album,
+    Composer,
+    Performer,
+    Genre,
+    Date,
+    Comment,
+    Track,
+    Disc,
+    File
+}
+
+impl Field {
+    pub const ALL: [Self; 12] = [
+        Self::Title,
+        Self::Artist,
+        Self::AlbumArtist,
+        Self::Album,
+        Self::Composer,
+        Self::Performer,
+        Self::Genre,
+        Self::Date,
+        Self::Comment,
+        Self::Track,
+        Self::Disc,
+        Self::File
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "title" => Some(Self::Title),
+            "artist" => Some(Self::Artist),
+            "albumartist" => Some(Self::AlbumArtist),
+            "album" => Some(Self::Album),
+            "composer" => Some(Self::Composer),
+            "performer" => Some(Self::Performer),
+            "genre" => Some(Self::Genre),
+            "date" => Some(Self::Date),
+            "comment" => Some(Self::Comment),
+            "track" => Some(Self::Track),
+            "disc" => Some(Self::Disc),
+            "file" | "path" => Some(Self::File),
+            _ => None
+        }
+    }
+
+    // the tag mpd sends it as
+    pub fn tag(self) -> &'static str {
+        match self {
+            Self::Title => "Title",
+            Self::Artist => "Artist",
+            Self::AlbumArtist => "AlbumArtist",
+            Self::Album => "Album",
+            Self::Composer => "Composer",
+            Self::Performer => "Performer",
+            Self::Genre => "Genre",
+            Self::Date => "Date",
+            Self::Comment => "Comment",
+            Self::Track => "Track",
+            Self::Disc => "Disc",
+            Self::File => "file"
+        }
+    }
+}
+
+// how a term's text is matched, fuzzy meaning whichever scorer is configured
+#[derive(Clone, Copy, PartialEq)]
+pub enum Matching {
+    Fuzzy,
+    Exact,
+    Regex
+}
+
+impl Matching {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Fuzzy => Self::Exact,
+            Self::Exact => Self::Regex,
+            Self::Regex => Self::Fuzzy
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Term {
+    pub field:    Option<Field>,
+    pub text:     String,
+    pub phrase:   bool,
+    pub negated:  bool,
+    pub matching: Matching
+}
+
+// whitespace separated terms which all have to match:
+//   word  "a phrase"  -not_this  artist:name  album:"a phrase"  -genre:pop  =exact  /a regex/  title:/^a/
+// plain words are matched with the default passed in, =word or /regex/ override it for that term
+pub fn parse(q: &str, default: Matching) -> Result<Vec<Term>> {
+    let mut terms = vec![];
+    let mut chars = q.chars().peekable();
+
+    loop {
+        while chars.next_if(|x| x.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(terms)
+        }
+
+        let negated = chars.next_if_eq(&'-').is_some();
+        let mut matching = prefix(&mut chars);
+
+        let mut field = None;
+        let mut word = String::new();
+        if matching != Some(Matching::Regex) {
+            while let Some(x) = chars.next_if(|x| !x.is_whitespace() && *x != '"' && *x != ':') {
+                word.push(x)
+            }
+        }
+
+        if matching != Some(Matching::Regex) && chars.next_if_eq(&':').is_some() {
+            match Field::from_name(&word) {
+                Some(x) => field = Some(x),
+                None if word.is_empty() => bail!("missing field name before ':'"),
+                None => bail!(
+                    "unknown field '{}', try title, artist, albumartist, album, composer, performer, genre, \
+                     date, comment, track, disc or path",
+                    word
+                )
+            }
+            matching = matching.or_else(|| prefix(&mut chars));
+            word = String::new();
+            if matching != Some(Matching::Regex) {
+                while let Some(x) = chars.next_if(|x| !x.is_whitespace() && *x != '"') {
+                    word.push(x)
+                }
+            }
+        }
+
+        let mut phrase = false;
+        if matching == Some(Matching::Regex) {
+            word = regex(&mut chars)?
+        }
+        else if word.is_empty() && chars.next_if_eq(&'"').is_some() {
+            phrase = true;
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(x) => word.push(x),
+                    None => bail!("missing closing '\"'")
+                }
+            }
+        }
+        else if chars.peek() == Some(&'"') {
+            bail!("unexpected '\"' after '{}'", word)
+        }
+
+        if word.is_empty() {
+            match (field, negated, matching) {
+                (_, _, Some(Matching::Regex)) => bail!("empty regex"),
+                (Some(_), ..) => bail!("nothing to search for after ':'"),
+                (None, _, Some(Matching::Exact)) => bail!("nothing to search for after '='"),
+                (None, true, _) => bail!("nothing to exclude after '-'"),
+                (None, false, _) => bail!("empty phrase")
+            }
+        }
+
+        terms.push(Term {
+            field,
+            text: word,
+            phrase,
+            negated,
+            matching: matching.unwrap_or(default)
+        })
+    }
+}
+
+fn prefix(chars: &mut Peekable<Chars>) -> Option<Matching> {
+    match chars.peek() {
+        Some('/') => chars.next().map(|_| Matching::Regex),
+        Some('=') => chars.next().map(|_| Matching::Exact),
+        _ => None
+    }
+}
+
+// everything up to the closing '/', which can be escaped as \/
+fn regex(chars: &mut Peekable<Chars>) -> Result<String> {
+    let mut re = String::new();
+    loop {
+        match chars.next() {
+            Some('/') => break,
+            Some('\\') if chars.peek() == Some(&'/') => re.extend(chars.next()),
+            Some(x) => re.push(x),
+            None => bail!("missing closing '/'")
+        }
+    }
+    if let Some(x) = chars.next_if(|x| !x.is_whitespace()) {
+        bail!("unexpected '{}' after the closing '/'", x)
+    }
+    Ok(re)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one(q: &str) -> Term {
+        let mut terms = parse(q, Matching::Fuzzy).unwrap();
+        assert_eq!(terms.len(), 1, "{}", q);
+        terms.remove(0)
+    }
+
+    fn error(q: &str) -> String {
+        parse(q, Matching::Fuzzy)
+            .err()
+            .map(|x| x.to_string())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn words_and_fields() {
+        let terms = parse("  love  artist:Kino path:rock/", Matching::Fuzzy).unwrap();
+        let texts = terms.iter().map(|x| x.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, ["love", "Kino", "rock/"]);
+        assert!(terms[0].field.is_none());
+        assert!(terms[1].field == Some(Field::Artist));
+        assert!(terms[2].field == Some(Field::File));
+        assert!(terms
+            .iter()
+            .all(|x| x.matching == Matching::Fuzzy && !x.phrase && !x.negated));
+    }
+
+    #[test]
+    fn phrases() {
+        let x = one("album:\"the wall\"");
+        assert!(x.phrase && x.field == Some(Field::Album));
+        assert_eq!(x.text, "the wall");
+        assert!(one("\"a : b\"").text == "a : b");
+    }
+
+    #[test]
+    fn negation() {
+        let x = one("-genre:pop");
+        assert!(x.negated && x.field == Some(Field::Genre));
+        assert!(one("-live").negated);
+    }
+
+    #[test]
+    fn exact_and_regex() {
+        let x = one("=Abba");
+        assert!(x.matching == Matching::Exact);
+        assert_eq!(x.text, "Abba");
+
+        let x = one("title:/^a\\/b c$/");
+        assert!(x.matching == Matching::Regex && x.field == Some(Field::Title));
+        assert_eq!(x.text, "^a/b c$");
+
+        let x = one("title:=Abba");
+        assert!(x.matching == Matching::Exact && x.field == Some(Field::Title));
+    }
+
+    #[test]
+    fn default_matching() {
+        assert!(parse("a", Matching::Regex).unwrap()[0].matching == Matching::Regex);
+        assert!(parse("=a", Matching::Regex).unwrap()[0].matching == Matching::Exact);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error(":a"), "missing field name before ':'");
+        assert!(error("colour:red").starts_with("unknown field 'colour'"));
+        assert_eq!(error("\"open"), "missing closing '\"'");
+        assert_eq!(error("a\"b\""), "unexpected '\"' after 'a'");
+        assert_eq!(error("//"), "empty regex");
+        assert_eq!(error("artist:"), "nothing to search for after ':'");
+        assert_eq!(error("= a"), "nothing to search for after '='");
+        assert_eq!(error("- a"), "nothing to exclude after '-'");
+        assert_eq!(error("\"\""), "empty phrase");
+        assert_eq!(error("/a"), "missing closing '/'");
+        assert_eq!(error("/a/b"), "unexpected 'b' after the closing '/'");
+    }
+}
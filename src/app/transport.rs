@@ -0,0 +1,74 @@
+use anyhow::{bail, Result};
+use mpd::{ReplayGain, State, Status};
+
+use super::conn::Connection;
+
+#[derive(Clone, Copy)]
+pub enum Control {
+    TogglePause,
+    Stop,
+    Next,
+    Previous,
+    // seconds either way from where the song is now
+    Seek(i64),
+    // seconds from the start of the song, for the progress bar
+    SeekTo(i64),
+    // percent either way
+    Volume(i8),
+    Repeat,
+    Random,
+    Single,
+    Consume,
+    Crossfade,
+    ReplayGain
+}
+
+// what the crossfade steps through, in seconds
+const CROSSFADES: [i64; 4] = [0, 2, 5, 10];
+
+// elapsed is in milliseconds and passed in, the status is only as fresh as the last idle event
+pub fn send(mpc: &mut Connection, control: Control, status: &Status, elapsed: i64) -> Result<()> {
+    match control {
+        Control::TogglePause => match status.state {
+            // pause doesn't start anything while stopped
+            State::Stop => mpc.run(|x| x.play()),
+            _ => mpc.run(|x| x.toggle_pause())
+        },
+        Control::Stop => mpc.run(|x| x.stop()),
+        Control::Next => mpc.run(|x| x.next()),
+        Control::Previous => mpc.run(|x| x.prev()),
+        Control::Seek(by) => send(mpc, Control::SeekTo(elapsed / 1000 + by), status, elapsed),
+        Control::SeekTo(to) => {
+            if status.state == State::Stop {
+                return Ok(())
+            }
+            let duration = status.duration.map(|x| x.num_seconds()).unwrap_or(0);
+            mpc.run(|x| x.rewind(to.clamp(0, duration)))
+        }
+        Control::Volume(by) => {
+            if status.volume < 0 {
+                bail!("No volume control for this output!")
+            }
+            let to = (status.volume as i16 + by as i16).clamp(0, 100) as i8;
+            mpc.run(|x| x.volume(to))
+        }
+        Control::Repeat => mpc.run(|x| x.repeat(!status.repeat)),
+        Control::Random => mpc.run(|x| x.random(!status.random)),
+        Control::Single => mpc.run(|x| x.single(!status.single)),
+        Control::Consume => mpc.run(|x| x.consume(!status.consume)),
+        Control::Crossfade => {
+            let now = status.crossfade.map(|x| x.num_seconds()).unwrap_or(0);
+            let next = CROSSFADES.into_iter().find(|x| *x > now).unwrap_or(0);
+            mpc.run(|x| x.crossfade(next))
+        }
+        Control::ReplayGain => {
+            let next = match status.replaygain {
+                Some(ReplayGain::Off) | None => ReplayGain::Track,
+                Some(ReplayGain::Track) => ReplayGain::Album,
+                Some(ReplayGain::Album) => ReplayGain::Auto,
+                Some(ReplayGain::Auto) => ReplayGain::Off
+            };
+            mpc.run(|x| x.replaygain(next))
+        }
+    }
+}
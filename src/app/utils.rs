@@ -2,54 +2,113 @@ use std::{
     env::var,
     fs::{self, File},
     io::{BufRead, BufReader},
-    net::TcpStream,
     path::Path
 };
 
 use anyhow::{bail, Result};
-use eframe::egui::Color32;
-use mpd::{Client, Song, Status};
+use eframe::egui::{
+    text::{LayoutJob, TextFormat},
+    Color32, TextStyle
+};
+use mpd::{
+    error::{Error, ErrorCode},
+    song::QueuePlace,
+    Id, ReplayGain, Song, Status
+};
+
+use super::{
+    cli,
+    config::Config,
+    conn::{self, Connection},
+    imp::SearchResult,
+    transport::Control
+};
 
-pub fn startup() -> Result<(Client<TcpStream>, Status, String)> {
-    let music_dir = find_music_dir()?;
-    let mut client = get_client()?;
-    let status = client.status()?;
-    match status.queue_len > 1 {
-        true => Ok((client, status, music_dir)),
-        false => bail!("Not enough songs in the queue!")
+pub fn startup() -> Result<(Connection, Status, String, Config)> {
+    let args = cli::parse()?;
+    let mut config = Config::load()?;
+    if let Some(x) = args.scorer {
+        config.scorer = x
     }
+    let settings = conn::Settings::load(&args, &config)?;
+    let mut client = Connection::new(settings)?;
+    let music_dir = match find_music_dir() {
+        Ok(x) => x,
+        Err(_) if client.settings.address.is_local() => client.run(|x| x.music_directory())?,
+        // covers come from mpd instead, see images::from_mpd
+        Err(_) => String::new()
+    };
+    let status = client.run(|x| x.status())?;
+    Ok((client, status, music_dir, config))
 }
 
-// TODO: add support for UnixStream client, this will require some restucturing of the app
-fn get_client() -> Result<Client<TcpStream>> { Ok(Client::new(TcpStream::connect("127.0.0.1:6600")?)?) }
-
 // mpd only allows directly reading the music directory from a (local) unix socket rather than TCP :(
 pub fn find_music_dir() -> Result<String> {
     let bail_msg = "Unable to determine music directory root!";
 
+    match mpd_conf_values("music_directory")?.first() {
+        Some(x) if x.starts_with('/') || x.starts_with('~') => Ok(expand_tilde(x)),
+        _ => bail!(bail_msg)
+    }
+}
+
+pub fn mpd_conf_values(key: &str) -> Result<Vec<String>> {
     let prefix = var("XDG_CONFIG_HOME").unwrap_or_else(|_| [&var("HOME").unwrap(), ".config"].join("/"));
     let mpd_conf_path = Path::new(&[&prefix, "mpd", "mpd.conf"].join("/")).to_owned();
     let mpd_conf = File::open(mpd_conf_path)?;
 
+    // only top level settings, audio_output blocks and the like have their own "port" etc.
+    let mut depth = 0;
+    let mut values = vec![];
     for x in BufReader::new(mpd_conf).lines().flatten() {
-        if x.starts_with("music_directory") {
-            let value_vec = x.split_whitespace().collect::<Vec<_>>();
-            let mut value = value_vec[1].to_owned();
-            value.remove(0);
-            value.remove(value.len() - 1);
-            if value.starts_with('/') {
-                return Ok(value.to_owned())
+        let line = x.trim();
+        if line.ends_with('{') {
+            depth += 1;
+            continue
+        }
+        if line.starts_with('}') {
+            depth -= 1;
+            continue
+        }
+        if depth > 0 {
+            continue
+        }
+        if let Some(rest) = line.strip_prefix(key) {
+            if !rest.starts_with(char::is_whitespace) {
+                continue
             }
-            else if value.starts_with('~') {
-                value.remove(0);
-                return Ok([var("HOME").unwrap(), value].join(""))
+            // quoted up to the closing quote, a trailing # comment is allowed after it
+            let rest = rest.trim();
+            let value = match rest.strip_prefix('"') {
+                Some(x) => x.split('"').next().unwrap_or_default(),
+                None => rest.split('#').next().unwrap_or_default().trim()
+            };
+            if !value.is_empty() {
+                values.push(value.to_owned())
             }
-            else {
-                bail!(bail_msg)
+        }
+    }
+    Ok(values)
+}
+
+pub fn expand_tilde(path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some(x) => [var("HOME").unwrap(), x.to_owned()].join(""),
+        None => path.to_owned()
+    }
+}
+
+// by id rather than position, the queue may have been shuffled around since we last looked at it
+pub fn play(mpc: &mut Connection, id: u32) -> Result<()> {
+    match mpc.run(|x| x.switch(Id(id))) {
+        Ok(_) => Ok(()),
+        Err(e) => match e.downcast_ref::<Error>() {
+            Some(Error::Server(x)) if x.code == ErrorCode::NoExist => {
+                bail!("That song is no longer in the queue!")
             }
+            _ => Err(e)
         }
     }
-    bail!(bail_msg)
 }
 
 pub fn gen_theme(path: &str) {
@@ -84,37 +143,110 @@ base0F: \"B48EAD\"
 pub fn gen_title(song: &Song) -> String {
     match &song.title {
         Some(x) => x.to_owned(),
-        None => Path::new(&song.file)
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
+        None => file_name(&song.file).to_string()
+    }
+}
+
+pub fn file_name(path: &str) -> &str { Path::new(path).file_name().unwrap().to_str().unwrap() }
+
+// a result row with the matched characters picked out and any non-title matches tacked on the end
+pub fn gen_row(
+    result: &SearchResult, marked: bool, text: Color32, highlight: Color32, dim: Color32
+) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    if marked {
+        append_highlighted(&mut job, "• ", &[], highlight, highlight)
+    }
+    append_highlighted(&mut job, &result.title, &result.highlights, text, highlight);
+    for (i, (value, chars)) in result.extra.iter().enumerate() {
+        let separator = match i {
+            0 => "  ",
+            _ => " · "
+        };
+        append_highlighted(&mut job, separator, &[], dim, dim);
+        append_highlighted(&mut job, value, chars, dim, highlight)
+    }
+    job
+}
+
+fn append_highlighted(job: &mut LayoutJob, s: &str, chars: &[usize], colour: Color32, highlight: Color32) {
+    let format = |x: Color32| TextFormat {
+        style: TextStyle::Monospace,
+        color: x,
+        ..Default::default()
+    };
+
+    // group runs of same coloured chars so we don't end up with a section per char
+    let mut run = String::new();
+    let mut run_colour = colour;
+    for (i, c) in s.chars().enumerate() {
+        let x = match chars.contains(&i) {
+            true => highlight,
+            false => colour
+        };
+        if x != run_colour && !run.is_empty() {
+            job.append(&run, 0.0, format(run_colour));
+            run.clear()
+        }
+        run_colour = x;
+        run.push(c)
+    }
+    if !run.is_empty() {
+        job.append(&run, 0.0, format(run_colour))
     }
 }
 
 pub fn gen_switcher(cycle: u8, status: &Status, queue: &[Song]) -> String {
+    // the queue can change under us, so a missing song is just an empty title
+    let title = |x: Option<QueuePlace>| {
+        x.and_then(|y| queue.get(y.pos as usize))
+            .map(gen_title)
+            .unwrap_or_default()
+    };
     match cycle {
-        0 => ["聾  ", &gen_title(&queue[status.song.unwrap().pos as usize])].join(""),
-        1 => ["  ", &gen_title(&queue[status.song.unwrap().pos as usize])].join(""),
-        2 => ["嶺  ", &gen_title(&queue[status.nextsong.unwrap().pos as usize])].join(""),
-        3 => [
-            "墳 ",
-            &status.volume.to_string(),
-            "  凌 ",
-            state_string(status.repeat),
-            "  咽 ",
-            state_string(status.random),
-            "  綾 ",
-            state_string(status.single),
-            "  裸 ",
-            state_string(status.consume)
-        ]
-        .join(""),
+        0 => ["聾  ", &title(status.song)].join(""),
+        1 => ["  ", &title(status.song)].join(""),
+        2 => ["嶺  ", &title(status.nextsong)].join(""),
+        3 => gen_options(status)
+            .into_iter()
+            .map(|x| x.0)
+            .collect::<Vec<_>>()
+            .join("  "),
         _ => unreachable!()
     }
 }
 
+// the parts of the options line, whether each is on (shown by colour to keep the line short) and what
+// clicking it does
+pub fn gen_options(status: &Status) -> Vec<(String, bool, Option<Control>)> {
+    let volume = match status.volume < 0 {
+        true => "-".to_string(),
+        false => status.volume.to_string()
+    };
+    let crossfade = status.crossfade.map(|x| x.num_seconds()).unwrap_or(0);
+    let replaygain = status.replaygain.unwrap_or(ReplayGain::Off);
+    vec![
+        (["墳 ", &volume].join(""), true, None),
+        ("凌".to_string(), status.repeat, Some(Control::Repeat)),
+        ("咽".to_string(), status.random, Some(Control::Random)),
+        ("綾".to_string(), status.single, Some(Control::Single)),
+        ("裸".to_string(), status.consume, Some(Control::Consume)),
+        (
+            match crossfade {
+                0 => " off".to_string(),
+                x => [" ", &x.to_string(), "s"].join("")
+            },
+            crossfade > 0,
+            Some(Control::Crossfade)
+        ),
+        (
+            [" ", &replaygain.to_string()].join(""),
+            replaygain != ReplayGain::Off,
+            Some(Control::ReplayGain)
+        ),
+    ]
+}
+
 pub fn gen_colour(s: &str) -> Color32 {
     let rgb = (0..s.len())
         .step_by(2)
@@ -187,11 +319,9 @@ pub fn progress_string(e: i64, d: i64) -> String {
     r.join("")
 }
 
-fn state_string(s: bool) -> &'static str {
-    if s {
-        "on"
-    }
-    else {
-        "off"
+pub fn ellipsize(s: &str, max: usize) -> String {
+    match s.chars().count() > max {
+        true => [s.chars().take(max - 2).collect::<String>(), " …".to_string()].join(""),
+        false => s.to_owned()
     }
 }
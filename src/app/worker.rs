@@ -0,0 +1,141 @@
+use std::{
+    sync::{mpsc::Sender, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant}
+};
+
+use anyhow::Result;
+use eframe::epi::{Frame, Image};
+use mpd::{Idle, ReplayGain, Song, State, Status, Subsystem};
+
+use super::{
+    conn::{self, Settings},
+    imp::SearchResult,
+    library,
+    proto::Raw,
+    search::Searcher
+};
+
+pub enum Event {
+    Library(Result<Vec<Song>>),
+    // songs whose position changed since the last version we saw, and the new queue length
+    Queue(Vec<Song>, usize),
+    Status(Box<Status>),
+    // the generation from Searcher::query and its results
+    Results(usize, Result<Vec<SearchResult>>),
+    // a song's file and its cover, see images::Loader
+    Cover(String, Image)
+}
+
+// when the current song started playing (ignoring pauses), None while paused or stopped. notified
+// whenever it's set
+type Clock = Arc<(Mutex<Option<Instant>>, Condvar)>;
+
+// mpd tells us when something changes on a second connection, so the ui only has to wake up for that
+// and for the progress clock
+pub fn spawn(settings: Settings, mut version: u32, tx: Sender<Event>, frame: Frame) {
+    let clock: Clock = Arc::new((Mutex::new(None), Condvar::new()));
+
+    let listener_clock = clock.clone();
+    let listener_frame = frame.clone();
+    thread::spawn(move || {
+        // nothing to report a lost connection to, just keep trying until the window closes
+        while listen(&settings, &mut version, &tx, &listener_clock, &listener_frame).is_err() {
+            thread::sleep(Duration::from_secs(1))
+        }
+    });
+
+    thread::spawn(move || tick(&clock, &frame));
+}
+
+pub fn load_library(settings: Settings, tx: Sender<Event>, searcher: Searcher, frame: Frame) {
+    thread::spawn(move || {
+        let library = library::load(&settings);
+        if let Ok(x) = &library {
+            searcher.set_library(x)
+        }
+        if tx.send(Event::Library(library)).is_ok() {
+            frame.request_repaint()
+        }
+    });
+}
+
+fn listen(
+    settings: &Settings, version: &mut u32, tx: &Sender<Event>, clock: &Clock, frame: &Frame
+) -> Result<()> {
+    let mut mpc = conn::connect(settings)?;
+    let mut changed = vec![Subsystem::Options];
+    let mut replaygain = None;
+    loop {
+        let mut status = mpc.status()?;
+        if changed.contains(&Subsystem::Options) {
+            // it takes a connection of its own, failing only leaves replay gain off the options line while
+            // the status still has to go out
+            replaygain = replay_gain_mode(settings).unwrap_or_default()
+        }
+        status.replaygain = replaygain;
+
+        if status.queue_version != *version {
+            let changes = mpc.changes(*version)?;
+            if tx.send(Event::Queue(changes, status.queue_len as usize)).is_err() {
+                return Ok(())
+            }
+            *version = status.queue_version
+        }
+
+        let (started, changed_state) = &**clock;
+        *started.lock().unwrap() = match (status.state, status.elapsed) {
+            (State::Play, Some(x)) => {
+                Instant::now().checked_sub(Duration::from_millis(x.num_milliseconds() as u64))
+            }
+            _ => None
+        };
+        changed_state.notify_all();
+
+        if tx.send(Event::Status(Box::new(status))).is_err() {
+            return Ok(())
+        }
+        frame.request_repaint();
+
+        changed = mpc.wait(&[
+            Subsystem::Player,
+            Subsystem::Mixer,
+            Subsystem::Options,
+            Subsystem::Queue
+        ])?;
+    }
+}
+
+// status has a field for this but mpd only says with replay_gain_status, which the mpd crate can't send.
+// only asked when the options change so a short lived connection is fine
+fn replay_gain_mode(settings: &Settings) -> Result<Option<ReplayGain>> {
+    Ok(Raw::connect(settings)?
+        .command("replay_gain_status", &[])?
+        .into_iter()
+        .find(|(key, _)| key == "replay_gain_mode")
+        .and_then(|(_, value)| value.parse().ok()))
+}
+
+// repaint as the progress clock rolls over to the next second, only while playing
+fn tick(clock: &Clock, frame: &Frame) {
+    let (started, changed_state) = &**clock;
+    loop {
+        let wait = {
+            let mut x = started.lock().unwrap();
+            while x.is_none() {
+                x = changed_state.wait(x).unwrap()
+            }
+            1000 - (x.unwrap().elapsed().as_millis() % 1000) as u64
+        };
+        thread::sleep(Duration::from_millis(wait + 5));
+        frame.request_repaint()
+    }
+}
+
+// egui can't be asked to repaint later, so this waits for it
+pub fn repaint_at(when: Instant, frame: Frame) {
+    thread::spawn(move || {
+        thread::sleep(when.saturating_duration_since(Instant::now()));
+        frame.request_repaint()
+    });
+}